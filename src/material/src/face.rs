@@ -1,8 +1,6 @@
-use crate::V2;
-
 pub struct Face {
 	pub vid: [usize; 3],
-	pub uvid: [usize; 3],
+	pub uv: [[f32; 2]; 3],
 }
 
 #[derive(Default)]
@@ -10,7 +8,7 @@ pub struct FaceGroup {
 	pub faces: Vec<Face>,
 }
 
+#[derive(Clone)]
 pub struct TextureData {
 	pub image: image::ImageBuffer<image::Rgba<u8>, Vec<u8>>,
-	pub tex_coords: Vec<V2>,
 }
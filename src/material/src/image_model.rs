@@ -4,13 +4,15 @@ use crate::face::TextureData;
 use crate::texture_indexer::FaceInfo;
 use crate::V2;
 use xpbd::constraint::constraint_template::ConstraintTemplate::{
-	Distance, Volume,
+	Distance, ShapeMatch, Volume,
 };
 use xpbd::constraint::distance::{
 	DistanceConstraintTemplate, DistanceConstraintType as DCTy,
 };
+use xpbd::constraint::shape_match::ShapeMatchConstraintTemplate;
 use xpbd::constraint::volume::VolumeConstraintTemplate;
-use xpbd::particle::ParticleTemplate;
+use xpbd::hierarchy::HierarchyTemplate;
+use xpbd::particle::{ParticleTemplate, ParticleType, Surface};
 use xpbd::physical_model::PhysicalModel;
 
 #[derive(Clone)]
@@ -23,7 +25,8 @@ struct Cell {
 pub struct ImageModelBuilder {
 	len: [isize; 2],
 	grid_size: [isize; 2],
-	csize: f32,
+	// lattice spacing, also particle diameter
+	spacing: f32,
 	texture_id: i32,
 	image: image::ImageBuffer<image::Rgba<u8>, Vec<u8>>,
 	faces: HashMap<usize, FaceInfo>,
@@ -31,6 +34,15 @@ pub struct ImageModelBuilder {
 	particles: Vec<ParticleTemplate>,
 	pid_alloc: usize,
 	tex_coords: Vec<V2>,
+	// number of coarse levels for hpbd, none by default
+	levels: usize,
+	break_range: Option<[f32; 2]>,
+	// compliance of shape matching over all particles
+	shape_match: Option<f32>,
+	surface: Surface,
+	// of lattice distance constraints
+	compliance: f32,
+	imass: f32,
 }
 
 impl ImageModelBuilder {
@@ -43,7 +55,7 @@ impl ImageModelBuilder {
 		Self {
 			len,
 			grid_size: [1024 / len[0], 1024 / len[1]],
-			csize: 0.08,
+			spacing: 0.08,
 			texture_id,
 			faces: Default::default(),
 			image,
@@ -51,9 +63,56 @@ impl ImageModelBuilder {
 			particles: Vec::new(),
 			pid_alloc: 0,
 			tex_coords: Vec::new(),
+			levels: 0,
+			break_range: None,
+			shape_match: None,
+			surface: Default::default(),
+			compliance: 1e-5,
+			imass: 1.0,
 		}
 	}
 
+	// lattice constraints break when stretched out of range, relative to l0
+	pub fn with_break_range(mut self, range: [f32; 2]) -> Self {
+		self.break_range = Some(range);
+		self
+	}
+
+	// whole image as one rigid(0.0) or semi-rigid body
+	pub fn with_shape_match(mut self, compliance: f32) -> Self {
+		self.shape_match = Some(compliance);
+		self
+	}
+
+	// lattice edges, area constraints are kept stiff
+	pub fn with_compliance(mut self, compliance: f32) -> Self {
+		self.compliance = compliance;
+		self
+	}
+
+	// call before adding particles
+	pub fn with_imass(mut self, imass: f32) -> Self {
+		self.imass = imass;
+		self
+	}
+
+	pub fn with_surface(mut self, surface: Surface) -> Self {
+		self.surface = surface;
+		self
+	}
+
+	// call before adding particles
+	pub fn with_spacing(mut self, spacing: f32) -> Self {
+		self.spacing = spacing;
+		self
+	}
+
+	// only used by PWorld::with_hpbd
+	pub fn with_levels(mut self, levels: usize) -> Self {
+		self.levels = levels;
+		self
+	}
+
 	pub fn add_particle(&mut self, idx: isize, idy: isize, color_check: bool) {
 		let x = (idx * self.grid_size[0]) as u32;
 		let y = (idy * self.grid_size[1]) as u32;
@@ -63,9 +122,15 @@ impl ImageModelBuilder {
 				return;
 			}
 		}
-		let imass = 1.0;
-		let pos = V2::new(self.csize * idx as f32, self.csize * idy as f32);
-		let p = ParticleTemplate { imass, pos };
+		let imass = self.imass;
+		let pos = V2::new(self.spacing * idx as f32, self.spacing * idy as f32);
+		let p = ParticleTemplate {
+			imass,
+			pos,
+			radius: self.spacing / 2.0,
+			ty: ParticleType::Solid,
+			surface: self.surface,
+		};
 		self.particles.push(p);
 		self.cells[idx as usize][idy as usize] = Some(Cell {
 			pid: self.pid_alloc,
@@ -136,6 +201,129 @@ impl ImageModelBuilder {
 		result
 	}
 
+	fn get_pid(&self, idx: isize, idy: isize) -> Option<usize> {
+		if idx < 0 || idx >= self.len[0] || idy < 0 || idy >= self.len[1] {
+			return None;
+		}
+		self.cells[idx as usize][idy as usize]
+			.as_ref()
+			.map(|cell| cell.pid)
+	}
+
+	// coarse particles are on grid points of multiple of stride
+	// a coarse edge is stride fine edges in series, dcmap is of those
+	fn build_level(
+		&self,
+		stride: isize,
+		dcmap: &HashMap<[usize; 2], usize>,
+	) -> HierarchyTemplate {
+		let mut coarse = vec![];
+		let mut coarse_map = HashMap::new();
+		let mut constraints = vec![];
+		let mut spans = vec![];
+		let mut children = vec![];
+		for idx in (0..self.len[0]).step_by(stride as usize) {
+			for idy in (0..self.len[1]).step_by(stride as usize) {
+				if let Some(pid) = self.get_pid(idx, idy) {
+					coarse_map.insert(pid, coarse.len());
+					coarse.push(pid);
+				}
+			}
+		}
+		let offset_pairs = [
+			[[0, 0], [-1, 0]],
+			[[0, 0], [0, -1]],
+			[[0, 0], [-1, -1]],
+			[[0, -1], [-1, 0]],
+		];
+		for idx in (0..self.len[0]).step_by(stride as usize) {
+			for idy in (0..self.len[1]).step_by(stride as usize) {
+				for [o0, o1] in offset_pairs.iter() {
+					// fine edges from o0 to o1, none missing
+					let span: Option<Vec<usize>> = (0..stride)
+						.map(|k| {
+							let pid = |k: isize| {
+								self.get_pid(
+									idx + o0[0] * stride + (o1[0] - o0[0]) * k,
+									idy + o0[1] * stride + (o1[1] - o0[1]) * k,
+								)
+							};
+							let mut ids = [pid(k)?, pid(k + 1)?];
+							ids.sort_unstable();
+							dcmap.get(&ids).copied()
+						})
+						.collect();
+					let span = match span {
+						Some(span) => span,
+						None => continue,
+					};
+					let pids: Vec<usize> = [o0, o1]
+						.iter()
+						.filter_map(|o| {
+							self.get_pid(
+								idx + o[0] * stride,
+								idy + o[1] * stride,
+							)
+						})
+						.collect();
+					let pos0 = self.particles[pids[0]].pos;
+					let pos1 = self.particles[pids[1]].pos;
+					constraints.push(Distance(DistanceConstraintTemplate {
+						l0: (pos0 - pos1).magnitude(),
+						ps: pids,
+						compliance: self.compliance * stride as f32,
+						ty: DCTy::Attractive,
+						break_range: None,
+					}));
+					spans.push(span);
+				}
+			}
+		}
+		for idx in 0..self.len[0] {
+			for idy in 0..self.len[1] {
+				let pid = match self.get_pid(idx, idy) {
+					Some(pid) => pid,
+					None => continue,
+				};
+				if coarse_map.contains_key(&pid) {
+					continue;
+				}
+				// bilinear weights of surrounding coarse particles
+				let bx = idx - idx % stride;
+				let by = idy - idy % stride;
+				let fx = (idx - bx) as f32 / stride as f32;
+				let fy = (idy - by) as f32 / stride as f32;
+				let mut weights = vec![];
+				for (dx, dy, w) in [
+					(0, 0, (1. - fx) * (1. - fy)),
+					(1, 0, fx * (1. - fy)),
+					(0, 1, (1. - fx) * fy),
+					(1, 1, fx * fy),
+				] {
+					if w == 0. {
+						continue;
+					}
+					let cpid = self.get_pid(bx + dx * stride, by + dy * stride);
+					if let Some(cidx) = cpid.and_then(|x| coarse_map.get(&x)) {
+						weights.push((*cidx, w));
+					}
+				}
+				let sum: f32 = weights.iter().map(|x| x.1).sum();
+				if sum == 0. {
+					continue;
+				}
+				weights.iter_mut().for_each(|x| x.1 /= sum);
+				children.push((pid, weights));
+			}
+		}
+		HierarchyTemplate {
+			coarse,
+			constraints,
+			spans,
+			children,
+		}
+	}
+
 	pub fn build_physical_model(&mut self) -> PhysicalModel {
 		let mut constraints = vec![];
 		let mut dcmap = HashMap::new();
@@ -153,8 +341,9 @@ impl ImageModelBuilder {
 			let dc = DistanceConstraintTemplate {
 				l0: (pos0 - pos1).magnitude(),
 				ps: vec![id0, id1],
-				compliance: 1e-5,
+				compliance: self.compliance,
 				ty: DCTy::Attractive,
+				break_range: self.break_range,
 			};
 			let mut ids = [id0, id1];
 			ids.sort_unstable();
@@ -184,7 +373,11 @@ impl ImageModelBuilder {
 
 			let face_info = FaceInfo {
 				texture_id: self.texture_id,
-				uvid: ps.clone().try_into().unwrap(),
+				uv: [
+					self.tex_coords[id0],
+					self.tex_coords[id1],
+					self.tex_coords[id2],
+				],
 			};
 			self.faces.insert(constraints.len(), face_info);
 			let vc = VolumeConstraintTemplate {
@@ -193,18 +386,26 @@ impl ImageModelBuilder {
 			};
 			constraints.push(Volume(vc));
 		});
+		if let Some(compliance) = self.shape_match {
+			constraints.push(ShapeMatch(ShapeMatchConstraintTemplate {
+				ps: (0..self.particles.len()).collect(),
+				compliance,
+			}));
+		}
+		let levels = (1..=self.levels)
+			.map(|level| self.build_level(1 << level, &dcmap))
+			.collect();
 		PhysicalModel {
 			particles: std::mem::take(&mut self.particles),
 			constraints,
 			dependencies: deps,
+			levels,
+			collision: Default::default(),
 		}
 	}
 
 	pub fn finish(self) -> (TextureData, HashMap<usize, FaceInfo>) {
-		let td = TextureData {
-			tex_coords: self.tex_coords,
-			image: self.image,
-		};
+		let td = TextureData { image: self.image };
 		(td, self.faces)
 	}
 }
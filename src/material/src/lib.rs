@@ -1,6 +1,12 @@
 pub mod face;
 pub mod image_model;
+pub mod mesh;
+pub mod model_file;
+pub mod polygon_model;
 pub mod render_model;
+pub mod scene;
+pub mod sdf_image;
+pub mod svg;
 pub mod texture_indexer;
 
 use xpbd::V2;
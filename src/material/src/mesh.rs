@@ -0,0 +1,332 @@
+// conforming Delaunay triangulation of polygons with holes
+// boundary is resampled, interior is filled with a triangular lattice,
+// boundary segments encroached by other points are split, so every
+// segment is a Delaunay edge and the mesh follows the outlines
+// inside is decided by even-odd rule over all outlines
+
+use std::collections::HashMap;
+
+use crate::V2;
+
+type P = [f64; 2];
+
+pub struct Mesh {
+	pub points: Vec<V2>,
+	// counter-clockwise in y-up coordinates
+	pub triangles: Vec<[usize; 3]>,
+}
+
+fn sub(a: P, b: P) -> P {
+	[a[0] - b[0], a[1] - b[1]]
+}
+
+fn dist2(a: P, b: P) -> f64 {
+	let d = sub(a, b);
+	d[0] * d[0] + d[1] * d[1]
+}
+
+fn cross(a: P, b: P, c: P) -> f64 {
+	let u = sub(b, a);
+	let v = sub(c, a);
+	u[0] * v[1] - u[1] * v[0]
+}
+
+fn segment_dist2(p: P, a: P, b: P) -> f64 {
+	let e = sub(b, a);
+	let l2 = e[0] * e[0] + e[1] * e[1];
+	if l2 == 0.0 {
+		return dist2(p, a);
+	}
+	let d = sub(p, a);
+	let t = ((d[0] * e[0] + d[1] * e[1]) / l2).clamp(0.0, 1.0);
+	dist2(p, [a[0] + e[0] * t, a[1] + e[1] * t])
+}
+
+struct Boundary {
+	points: Vec<P>,
+	segments: Vec<[usize; 2]>,
+}
+
+impl Boundary {
+	// outline edges split into pieces no longer than spacing
+	fn new(outlines: &[Vec<V2>], spacing: f64) -> Self {
+		let mut points = Vec::new();
+		let mut segments = Vec::new();
+		for outline in outlines.iter() {
+			let mut ring: Vec<P> = Vec::new();
+			for v in outline.iter() {
+				let p = [v[0] as f64, v[1] as f64];
+				if !matches!(ring.last(), Some(&q) if dist2(p, q) <= 1e-12) {
+					ring.push(p);
+				}
+			}
+			while ring.len() > 1
+				&& dist2(ring[0], *ring.last().unwrap()) <= 1e-12
+			{
+				ring.pop();
+			}
+			if ring.len() < 3 {
+				continue;
+			}
+			let first = points.len();
+			for i in 0..ring.len() {
+				let (a, b) = (ring[i], ring[(i + 1) % ring.len()]);
+				let n = (dist2(a, b).sqrt() / spacing).ceil().max(1.0) as usize;
+				for k in 0..n {
+					let t = k as f64 / n as f64;
+					points.push([
+						a[0] + (b[0] - a[0]) * t,
+						a[1] + (b[1] - a[1]) * t,
+					]);
+				}
+			}
+			let last = points.len();
+			for i in first..last {
+				let j = if i + 1 == last { first } else { i + 1 };
+				segments.push([i, j]);
+			}
+		}
+		Self { points, segments }
+	}
+
+	fn inside(&self, p: P) -> bool {
+		let mut result = false;
+		for &[i, j] in self.segments.iter() {
+			let (a, b) = (self.points[i], self.points[j]);
+			if (a[1] > p[1]) != (b[1] > p[1]) {
+				let x = a[0] + (p[1] - a[1]) / (b[1] - a[1]) * (b[0] - a[0]);
+				if p[0] < x {
+					result = !result;
+				}
+			}
+		}
+		result
+	}
+
+	fn dist2(&self, p: P) -> f64 {
+		self.segments
+			.iter()
+			.map(|&[i, j]| segment_dist2(p, self.points[i], self.points[j]))
+			.fold(f64::INFINITY, f64::min)
+	}
+
+	fn aabb(&self) -> [P; 2] {
+		let mut min = [f64::INFINITY; 2];
+		let mut max = [f64::NEG_INFINITY; 2];
+		for p in self.points.iter() {
+			for k in 0..2 {
+				min[k] = min[k].min(p[k]);
+				max[k] = max[k].max(p[k]);
+			}
+		}
+		[min, max]
+	}
+
+	// split segments with another point in their diametral circle,
+	// interior points in such a circle are dropped instead
+	fn split_encroached(&mut self, interior: &mut Vec<P>, min_length: f64) {
+		let encroach = |a: P, b: P, p: P| {
+			let mid = [(a[0] + b[0]) / 2.0, (a[1] + b[1]) / 2.0];
+			dist2(p, mid) < dist2(a, b) / 4.0 * (1.0 - 1e-9)
+		};
+		// a small input angle can keep splitting, so passes are limited
+		for _ in 0..32 {
+			let mut changed = false;
+			let mut idx = 0;
+			while idx < self.segments.len() {
+				let [i, j] = self.segments[idx];
+				let (a, b) = (self.points[i], self.points[j]);
+				interior.retain(|&p| !encroach(a, b, p));
+				let split = dist2(a, b) > min_length * min_length
+					&& (0..self.points.len()).any(|k| {
+						k != i && k != j && encroach(a, b, self.points[k])
+					});
+				if split {
+					let m = self.points.len();
+					self.points
+						.push([(a[0] + b[0]) / 2.0, (a[1] + b[1]) / 2.0]);
+					self.segments[idx] = [i, m];
+					self.segments.push([m, j]);
+					changed = true;
+				} else {
+					idx += 1;
+				}
+			}
+			if !changed {
+				break;
+			}
+		}
+	}
+}
+
+struct Triangle {
+	v: [usize; 3],
+	center: P,
+	r2: f64,
+}
+
+impl Triangle {
+	fn new(v: [usize; 3], points: &[P]) -> Self {
+		let [a, b, c] = v.map(|i| points[i]);
+		let d = 2.0 * cross(a, b, c);
+		let (center, r2) = if d.abs() < 1e-18 {
+			// degenerate, removed by the next point
+			(a, f64::INFINITY)
+		} else {
+			let b = sub(b, a);
+			let c = sub(c, a);
+			let lb = b[0] * b[0] + b[1] * b[1];
+			let lc = c[0] * c[0] + c[1] * c[1];
+			let x = (c[1] * lb - b[1] * lc) / d;
+			let y = (b[0] * lc - c[0] * lb) / d;
+			([a[0] + x, a[1] + y], x * x + y * y)
+		};
+		Self { v, center, r2 }
+	}
+}
+
+// Bowyer-Watson, returns triangles not touching the super triangle
+fn delaunay(points: &[P]) -> Vec<[usize; 3]> {
+	let n = points.len();
+	let mut all = points.to_vec();
+	let [min, max] = points.iter().fold(
+		[[f64::INFINITY; 2], [f64::NEG_INFINITY; 2]],
+		|[min, max], p| {
+			[
+				[min[0].min(p[0]), min[1].min(p[1])],
+				[max[0].max(p[0]), max[1].max(p[1])],
+			]
+		},
+	);
+	let c = [(min[0] + max[0]) / 2.0, (min[1] + max[1]) / 2.0];
+	let r = (max[0] - min[0]).max(max[1] - min[1]).max(1e-6) * 100.0;
+	all.push([c[0] - r, c[1] - r]);
+	all.push([c[0] + r, c[1] - r]);
+	all.push([c[0], c[1] + r]);
+	let mut triangles = vec![Triangle::new([n, n + 1, n + 2], &all)];
+	for i in 0..n {
+		let p = all[i];
+		let mut edges: HashMap<[usize; 2], usize> = HashMap::new();
+		triangles.retain(|t| {
+			if dist2(p, t.center) >= t.r2 {
+				return true;
+			}
+			for k in 0..3 {
+				let mut e = [t.v[k], t.v[(k + 1) % 3]];
+				e.sort_unstable();
+				*edges.entry(e).or_insert(0) += 1;
+			}
+			false
+		});
+		// edges of the cavity
+		let mut cavity: Vec<[usize; 2]> = edges
+			.into_iter()
+			.filter(|&(_, count)| count == 1)
+			.map(|(e, _)| e)
+			.collect();
+		cavity.sort_unstable();
+		for [a, b] in cavity.into_iter() {
+			triangles.push(Triangle::new([a, b, i], &all));
+		}
+	}
+	triangles
+		.into_iter()
+		.map(|t| t.v)
+		.filter(|v| v.iter().all(|&i| i < n))
+		.collect()
+}
+
+// spacing: target edge length
+pub fn triangulate(outlines: &[Vec<V2>], spacing: f32) -> Mesh {
+	let s = spacing as f64;
+	let mut boundary = Boundary::new(outlines, s);
+	if boundary.segments.is_empty() {
+		return Mesh {
+			points: Vec::new(),
+			triangles: Vec::new(),
+		};
+	}
+	// triangular lattice, rows are shifted by half spacing
+	let [min, max] = boundary.aabb();
+	let dy = s * 3f64.sqrt() / 2.0;
+	let mut interior = Vec::new();
+	let mut row = 0;
+	let mut y = min[1] + dy / 2.0;
+	while y < max[1] {
+		let mut x = min[0] + if row % 2 == 0 { s / 2.0 } else { s };
+		while x < max[0] {
+			let p = [x, y];
+			if boundary.inside(p) && boundary.dist2(p) > s * s * 0.25 {
+				interior.push(p);
+			}
+			x += s;
+		}
+		y += dy;
+		row += 1;
+	}
+	boundary.split_encroached(&mut interior, s / 16.0);
+	let mut points = boundary.points.clone();
+	points.extend(interior);
+	let mut triangles: Vec<[usize; 3]> = delaunay(&points)
+		.into_iter()
+		.filter(|&[a, b, c]| {
+			let (a, b, c) = (points[a], points[b], points[c]);
+			let centroid =
+				[(a[0] + b[0] + c[0]) / 3.0, (a[1] + b[1] + c[1]) / 3.0];
+			cross(a, b, c).abs() > s * s * 1e-6 && boundary.inside(centroid)
+		})
+		.map(|[a, b, c]| {
+			if cross(points[a], points[b], points[c]) < 0.0 {
+				[a, c, b]
+			} else {
+				[a, b, c]
+			}
+		})
+		.collect();
+	// drop points of no triangle, e.g. on a zero width part
+	let mut map = vec![usize::MAX; points.len()];
+	let mut result = Vec::new();
+	for v in triangles.iter_mut().flatten() {
+		if map[*v] == usize::MAX {
+			map[*v] = result.len();
+			result.push(V2::new(points[*v][0] as f32, points[*v][1] as f32));
+		}
+		*v = map[*v];
+	}
+	Mesh {
+		points: result,
+		triangles,
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_square_with_hole() {
+		let square = |a: f32, b: f32| {
+			vec![V2::new(a, a), V2::new(b, a), V2::new(b, b), V2::new(a, b)]
+		};
+		let mesh = triangulate(&[square(0., 4.), square(1., 3.)], 0.3);
+		let mut area = 0.0;
+		let mut min_angle = f32::MAX;
+		for t in mesh.triangles.iter() {
+			let [a, b, c] = t.map(|i| mesh.points[i]);
+			let (u, v) = (b - a, c - a);
+			let twice = u[0] * v[1] - u[1] * v[0];
+			assert!(twice > 0.0);
+			area += twice / 2.0;
+			let centroid = (a + b + c) / 3.0;
+			assert!(
+				!(1. ..3.).contains(&centroid[0])
+					|| !(1. ..3.).contains(&centroid[1])
+			);
+			for (p, q, r) in [(a, b, c), (b, c, a), (c, a, b)] {
+				min_angle = min_angle.min((q - p).angle(&(r - p)));
+			}
+		}
+		assert!((area - 12.0).abs() < 1e-3);
+		assert!(min_angle > 20f32.to_radians());
+	}
+}
@@ -0,0 +1,237 @@
+// model_file: physical model with its textures, so it can be loaded
+// without rebuilding from an image
+// layout: magic, u32 version(little endian), then bincode of ModelData
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::Cursor;
+
+use crate::face::TextureData;
+use crate::image_model::ImageModelBuilder;
+use crate::texture_indexer::FaceInfo;
+use serde::{Deserialize, Serialize};
+use xpbd::physical_model::PhysicalModel;
+
+const MAGIC: &[u8; 4] = b"PSVM";
+// increase when ModelData changes, older files need a conversion
+const VERSION: u32 = 3;
+
+#[derive(Serialize, Deserialize)]
+struct EncodedTextures {
+	faces: HashMap<usize, FaceInfo>,
+	// png encoded
+	textures: Vec<Vec<u8>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ModelData {
+	model: PhysicalModel,
+	textures: EncodedTextures,
+}
+
+// faces and textures of a model, without the model
+// e.g. payload of ControllerMessage::AddModel
+pub struct ModelTextures {
+	// model constraint idx -> face, texture id is idx in textures
+	pub faces: HashMap<usize, FaceInfo>,
+	pub textures: Vec<TextureData>,
+}
+
+impl ModelTextures {
+	fn to_encoded(&self) -> Result<EncodedTextures, Box<dyn Error>> {
+		let mut textures = Vec::new();
+		for texture in self.textures.iter() {
+			let mut data = Cursor::new(Vec::new());
+			texture
+				.image
+				.write_to(&mut data, image::ImageOutputFormat::Png)?;
+			textures.push(data.into_inner());
+		}
+		Ok(EncodedTextures {
+			faces: self.faces.clone(),
+			textures,
+		})
+	}
+
+	fn from_encoded(data: EncodedTextures) -> Result<Self, Box<dyn Error>> {
+		let mut textures = Vec::new();
+		for texture in data.textures.iter() {
+			let image = image::load_from_memory(texture)?.into_rgba8();
+			textures.push(TextureData { image });
+		}
+		Ok(Self {
+			faces: data.faces,
+			textures,
+		})
+	}
+
+	pub fn encode(&self) -> Result<Vec<u8>, Box<dyn Error>> {
+		Ok(bincode::serialize(&self.to_encoded()?)?)
+	}
+
+	pub fn decode(data: &[u8]) -> Result<Self, Box<dyn Error>> {
+		Self::from_encoded(bincode::deserialize(data)?)
+	}
+
+	// texture ids are offset by base, e.g. number of loaded textures
+	pub fn offset_texture_ids(&mut self, base: i32) {
+		for face in self.faces.values_mut() {
+			face.texture_id += base;
+		}
+	}
+}
+
+pub struct ModelFile {
+	pub model: PhysicalModel,
+	pub textures: ModelTextures,
+}
+
+impl ModelFile {
+	// cells of builder should be computed
+	pub fn from_image_builder(mut builder: ImageModelBuilder) -> Self {
+		let model = builder.build_physical_model();
+		let (texture, mut faces) = builder.finish();
+		for face in faces.values_mut() {
+			face.texture_id = 0;
+		}
+		Self {
+			model,
+			textures: ModelTextures {
+				faces,
+				textures: vec![texture],
+			},
+		}
+	}
+
+	pub fn save(&self, path: &str) -> Result<(), Box<dyn Error>> {
+		let data = ModelData {
+			model: self.model.clone(),
+			textures: self.textures.to_encoded()?,
+		};
+		let mut bytes = MAGIC.to_vec();
+		bytes.extend(VERSION.to_le_bytes());
+		bytes.extend(bincode::serialize(&data)?);
+		std::fs::write(path, bytes)?;
+		Ok(())
+	}
+
+	pub fn load(path: &str) -> Result<Self, Box<dyn Error>> {
+		let bytes = std::fs::read(path)?;
+		if bytes.len() < 8 || &bytes[0..4] != MAGIC {
+			return Err(format!("{} is not a model file", path).into());
+		}
+		let version = u32::from_le_bytes(bytes[4..8].try_into().unwrap());
+		if version != VERSION {
+			return Err(format!(
+				"model file version {} is not supported, expect {}",
+				version, VERSION
+			)
+			.into());
+		}
+		let data: ModelData = bincode::deserialize(&bytes[8..])?;
+		Ok(Self {
+			model: data.model,
+			textures: ModelTextures::from_encoded(data.textures)?,
+		})
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::V2;
+	use xpbd::constraint::angle::AngleConstraintTemplate;
+	use xpbd::constraint::constraint_template::ConstraintTemplate;
+	use xpbd::constraint::distance::{
+		DistanceConstraintTemplate, DistanceConstraintType,
+	};
+	use xpbd::constraint::shape_match::ShapeMatchConstraintTemplate;
+	use xpbd::constraint::volume::VolumeConstraintTemplate;
+	use xpbd::hierarchy::HierarchyTemplate;
+	use xpbd::particle::ParticleTemplate;
+
+	// triangle with every kind of template
+	fn test_model() -> PhysicalModel {
+		let mut model = PhysicalModel::default();
+		for pos in [[0., 0.], [1., 0.], [0., 1.]] {
+			let p = ParticleTemplate::new(1.0, V2::from(pos), 0.5);
+			model.particles.push(p);
+		}
+		let distance =
+			ConstraintTemplate::Distance(DistanceConstraintTemplate {
+				ps: vec![0, 1],
+				l0: 1.0,
+				compliance: 1e-5,
+				ty: DistanceConstraintType::Attractive,
+				break_range: Some([0.5, 1.5]),
+			});
+		model.constraints = vec![
+			ConstraintTemplate::Volume(VolumeConstraintTemplate {
+				ps: vec![0, 1, 2],
+				compliance: 1e-7,
+			}),
+			distance.clone(),
+			ConstraintTemplate::Angle(AngleConstraintTemplate {
+				ps: vec![1, 0, 2],
+				a0: 1.5,
+				compliance: 1e-4,
+				limit: None,
+			}),
+			ConstraintTemplate::ShapeMatch(ShapeMatchConstraintTemplate {
+				ps: vec![0, 1, 2],
+				compliance: 0.0,
+			}),
+		];
+		model.dependencies.push([1, 0]);
+		model.levels.push(HierarchyTemplate {
+			coarse: vec![0, 1],
+			constraints: vec![distance],
+			spans: vec![vec![1]],
+			children: vec![(2, vec![(0, 1.0)])],
+		});
+		model
+	}
+
+	#[test]
+	fn test_save_load() {
+		let face = FaceInfo {
+			texture_id: 0,
+			uv: [V2::new(0., 0.), V2::new(1., 0.), V2::new(0., 1.)],
+		};
+		let image = image::ImageBuffer::from_pixel(4, 4, image::Rgba([1; 4]));
+		let file = ModelFile {
+			model: test_model(),
+			textures: ModelTextures {
+				faces: [(0, face)].into_iter().collect(),
+				textures: vec![TextureData { image }],
+			},
+		};
+		let name = format!("psva2d_test_model_{}.psvm", std::process::id());
+		let path = std::env::temp_dir().join(name);
+		let path = path.to_str().unwrap();
+		file.save(path).unwrap();
+		let bytes = std::fs::read(path).unwrap();
+		let mut loaded = ModelFile::load(path).unwrap();
+		std::fs::remove_file(path).unwrap();
+		assert_eq!(&bytes[0..4], b"PSVM");
+		assert_eq!(bytes[4..8], VERSION.to_le_bytes());
+		let model = bincode::serialize(&loaded.model).unwrap();
+		assert_eq!(model, bincode::serialize(&file.model).unwrap());
+		assert_eq!(loaded.model.particles.len(), 3);
+		assert_eq!(loaded.model.particles[2].pos, V2::new(0., 1.));
+		assert_eq!(loaded.model.constraints[0].particles(), &[0, 1, 2]);
+		assert_eq!(loaded.model.levels[0].spans, vec![vec![1]]);
+		let textures = &file.textures;
+		assert_eq!(
+			loaded.textures.textures[0].image,
+			textures.textures[0].image
+		);
+		loaded.textures.offset_texture_ids(2);
+		assert_eq!(loaded.textures.faces[&0].texture_id, 2);
+		assert_eq!(loaded.textures.faces[&0].uv, textures.faces[&0].uv);
+		let decoded = ModelTextures::decode(&textures.encode().unwrap());
+		let decoded = decoded.unwrap();
+		assert_eq!(decoded.textures[0].image, textures.textures[0].image);
+		assert_eq!(decoded.faces[&0].uv, textures.faces[&0].uv);
+	}
+}
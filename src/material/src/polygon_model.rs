@@ -0,0 +1,230 @@
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+
+use crate::mesh::triangulate;
+use crate::svg::parse_svg;
+use crate::texture_indexer::FaceInfo;
+use crate::V2;
+use xpbd::constraint::constraint_template::ConstraintTemplate::{
+	Distance, Volume,
+};
+use xpbd::constraint::distance::{
+	DistanceConstraintTemplate, DistanceConstraintType as DCTy,
+};
+use xpbd::constraint::volume::VolumeConstraintTemplate;
+use xpbd::particle::{ParticleTemplate, ParticleType, Surface};
+use xpbd::physical_model::PhysicalModel;
+
+// soft body from polygon outlines, outlines inside another are holes
+pub struct PolygonModelBuilder {
+	texture_id: i32,
+	outlines: Vec<Vec<V2>>,
+	// target edge length, also particle diameter
+	spacing: f32,
+	// of edge distance constraints
+	compliance: f32,
+	imass: f32,
+	break_range: Option<[f32; 2]>,
+	surface: Surface,
+	// area mapped to the whole texture, bounding box if not set
+	uv_rect: Option<[V2; 2]>,
+	faces: HashMap<usize, FaceInfo>,
+}
+
+impl PolygonModelBuilder {
+	pub fn new(texture_id: i32) -> Self {
+		Self {
+			texture_id,
+			outlines: Vec::new(),
+			spacing: 0.08,
+			compliance: 1e-5,
+			imass: 1.0,
+			break_range: None,
+			surface: Default::default(),
+			uv_rect: None,
+			faces: Default::default(),
+		}
+	}
+
+	pub fn with_spacing(mut self, spacing: f32) -> Self {
+		self.spacing = spacing;
+		self
+	}
+
+	pub fn with_compliance(mut self, compliance: f32) -> Self {
+		self.compliance = compliance;
+		self
+	}
+
+	pub fn with_imass(mut self, imass: f32) -> Self {
+		self.imass = imass;
+		self
+	}
+
+	// edges break when stretched out of range, relative to l0
+	pub fn with_break_range(mut self, range: [f32; 2]) -> Self {
+		self.break_range = Some(range);
+		self
+	}
+
+	pub fn with_surface(mut self, surface: Surface) -> Self {
+		self.surface = surface;
+		self
+	}
+
+	pub fn with_uv_rect(mut self, rect: [V2; 2]) -> Self {
+		self.uv_rect = Some(rect);
+		self
+	}
+
+	// closed, either orientation
+	pub fn add_outline(&mut self, outline: Vec<V2>) {
+		self.outlines.push(outline);
+	}
+
+	// svg user units are multiplied by scale, y axis points down in both
+	pub fn add_svg(
+		&mut self,
+		path: &str,
+		scale: f32,
+	) -> Result<(), Box<dyn Error>> {
+		eprintln!("INFO: Loading {}", path);
+		let outlines = parse_svg(&std::fs::read_to_string(path)?)?;
+		if outlines.is_empty() {
+			return Err(format!("no outline in {}", path).into());
+		}
+		for outline in outlines.into_iter() {
+			self.add_outline(outline.into_iter().map(|p| p * scale).collect());
+		}
+		Ok(())
+	}
+
+	fn uv_rect(&self) -> [V2; 2] {
+		if let Some(rect) = self.uv_rect {
+			return rect;
+		}
+		let mut ps = self.outlines.iter().flatten();
+		let p0 = ps.next().copied().unwrap_or_else(V2::zeros);
+		ps.fold([p0, p0], |[min, max], p| [min.inf(p), max.sup(p)])
+	}
+
+	pub fn build_physical_model(&mut self) -> PhysicalModel {
+		let mesh = triangulate(&self.outlines, self.spacing);
+		let [min, max] = self.uv_rect();
+		let size = (max - min).map(|x| if x == 0.0 { 1.0 } else { x });
+		let particles = mesh
+			.points
+			.iter()
+			.map(|&pos| ParticleTemplate {
+				imass: self.imass,
+				pos,
+				radius: self.spacing / 2.0,
+				ty: ParticleType::Solid,
+				surface: self.surface,
+			})
+			.collect();
+		let mut constraints = vec![];
+		// sorted, so the model does not change between runs
+		let mut edges = BTreeMap::new();
+		for t in mesh.triangles.iter() {
+			for i in 0..3 {
+				let mut ids = [t[i], t[(i + 1) % 3]];
+				ids.sort_unstable();
+				edges.insert(ids, 0);
+			}
+		}
+		for (ids, idx) in edges.iter_mut() {
+			*idx = constraints.len();
+			let l0 = (mesh.points[ids[0]] - mesh.points[ids[1]]).magnitude();
+			constraints.push(Distance(DistanceConstraintTemplate {
+				ps: ids.to_vec(),
+				l0,
+				compliance: self.compliance,
+				ty: DCTy::Attractive,
+				break_range: self.break_range,
+			}));
+		}
+		let mut deps = vec![];
+		for t in mesh.triangles.iter() {
+			let l = constraints.len();
+			for i in 0..3 {
+				let mut ids = [t[i], t[(i + 1) % 3]];
+				ids.sort_unstable();
+				deps.push([edges[&ids], l]);
+			}
+			let uv = t.map(|i| (mesh.points[i] - min).component_div(&size));
+			let face_info = FaceInfo {
+				texture_id: self.texture_id,
+				uv,
+			};
+			self.faces.insert(l, face_info);
+			constraints.push(Volume(VolumeConstraintTemplate {
+				ps: t.to_vec(),
+				compliance: 1e-7,
+			}));
+		}
+		PhysicalModel {
+			particles,
+			constraints,
+			dependencies: deps,
+			levels: Vec::new(),
+			collision: Default::default(),
+		}
+	}
+
+	// faces by model constraint idx
+	pub fn finish(self) -> HashMap<usize, FaceInfo> {
+		self.faces
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_build_hole() {
+		let square = |min: f32, max: f32| {
+			vec![
+				V2::new(min, min),
+				V2::new(max, min),
+				V2::new(max, max),
+				V2::new(min, max),
+			]
+		};
+		let mut builder = PolygonModelBuilder::new(3).with_spacing(0.1);
+		builder.add_outline(square(0.0, 1.0));
+		builder.add_outline(square(0.4, 0.6));
+		let model = builder.build_physical_model();
+		let (mut edges, mut faces) = (0, Vec::new());
+		for (idx, c) in model.constraints.iter().enumerate() {
+			match c {
+				Distance(_) => edges += 1,
+				Volume(ct) => faces.push((idx, ct.ps.clone())),
+				_ => panic!("unexpected constraint"),
+			}
+		}
+		// euler characteristic of a disk with one hole is 0
+		let n = model.particles.len();
+		assert_eq!(n + faces.len(), edges);
+		assert_eq!(model.dependencies.len(), 3 * faces.len());
+		for &[edge, face] in model.dependencies.iter() {
+			assert!(matches!(model.constraints[edge], Distance(_)));
+			assert!(matches!(model.constraints[face], Volume(_)));
+		}
+		let infos = builder.finish();
+		assert_eq!(infos.len(), faces.len());
+		for (idx, ps) in faces.into_iter() {
+			let pos = ps.iter().map(|&id| model.particles[id].pos);
+			let center = pos.clone().sum::<V2>() / 3.0;
+			let hole = (center - V2::new(0.5, 0.5)).amax() < 0.1;
+			assert!(!hole, "face in the hole at {:?}", center);
+			// the bounding box is the unit square, uv is the position
+			let info = &infos[&idx];
+			assert_eq!(info.texture_id, 3);
+			for (uv, p) in info.uv.iter().zip(pos) {
+				assert!((uv - p).magnitude() < 1e-5);
+			}
+		}
+	}
+}
@@ -0,0 +1,446 @@
+// scene: world settings and models in a RON file, e.g.
+// (
+// 	gravity: Some((0, 9.8)),
+// 	models: [
+// 		(source: Image("a.png"), position: (0, -4), break_range: Some((0, 1.3))),
+// 		(source: Fluid((40, 40)), position: (-5, -10), mass: Some(0.5)),
+// 		(source: Svg(path: "b.svg", scale: 0.01), position: (4, -4)),
+// 	],
+// 	obstacles: [
+// 		Shape((shape: Circle([0, -2], 1.0))),
+// 		Image(path: "c.png", origin: (-5, -3), cell: 0.01),
+// 	],
+// 	leashes: [(model: 0, particle: 0)],
+// )
+// relative image and model paths are relative to the scene file
+
+use std::error::Error;
+use std::path::Path;
+
+use crate::face::TextureData;
+use crate::image_model::ImageModelBuilder;
+use crate::model_file::ModelFile;
+use crate::polygon_model::PolygonModelBuilder;
+use crate::sdf_image::load_sdf;
+use crate::texture_indexer::TextureIndexer;
+use crate::V2;
+use image::imageops::FilterType;
+use serde::{Deserialize, Serialize};
+use xpbd::constraint::constraint_template::ConstraintTemplate;
+use xpbd::fluid_model::FluidModelBuilder;
+use xpbd::obstacle::{Obstacle, Shape};
+use xpbd::particle::Surface;
+use xpbd::physical_model::PhysicalModel;
+use xpbd::posbox::Posbox;
+use xpbd::pworld::PWorld;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Source {
+	// 1024x1024 image, transparent pixels are empty
+	Image(String),
+	// rectangle of size[0] x size[1] fluid particles
+	Fluid([usize; 2]),
+	// saved by ModelFile
+	Model(String),
+	// outlines of an svg file, triangulated
+	Svg {
+		path: String,
+		// world units per svg unit
+		scale: f32,
+		// edge length, default of PolygonModelBuilder if not set
+		#[serde(default)]
+		spacing: Option<f32>,
+		// image stretched over the bounding box of outlines
+		#[serde(default)]
+		texture: Option<String>,
+	},
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SceneModel {
+	pub source: Source,
+	#[serde(default)]
+	pub position: [f32; 2],
+	// of distance constraints, default of the source if not set
+	#[serde(default)]
+	pub compliance: Option<f32>,
+	// of each particle
+	#[serde(default)]
+	pub mass: Option<f32>,
+	// relative to rest length
+	#[serde(default)]
+	pub break_range: Option<[f32; 2]>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub enum SceneObstacle {
+	Shape(Obstacle),
+	// sdf of an image, pixels with nonzero alpha are solid
+	Image {
+		path: String,
+		// world position of the top left pixel corner
+		origin: [f32; 2],
+		// world size of a pixel
+		cell: f32,
+		#[serde(default)]
+		surface: Surface,
+	},
+}
+
+// particle held at pos from the start
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SceneLeash {
+	// idx in scene models
+	pub model: usize,
+	// model particle idx
+	pub particle: usize,
+	// current position if not set
+	#[serde(default)]
+	pub pos: Option<[f32; 2]>,
+}
+
+// world settings are world defaults if not set
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Scene {
+	pub dt: Option<f32>,
+	pub ppr: Option<usize>,
+	pub iteration: Option<usize>,
+	pub posbox: Posbox,
+	pub gravity: Option<[f32; 2]>,
+	pub models: Vec<SceneModel>,
+	pub obstacles: Vec<SceneObstacle>,
+	pub leashes: Vec<SceneLeash>,
+}
+
+impl Default for Scene {
+	fn default() -> Self {
+		Self {
+			dt: None,
+			ppr: None,
+			iteration: None,
+			posbox: Posbox {
+				xmin: -15.0,
+				xmax: 15.0,
+				ymin: -30.0,
+				ymax: 0.,
+			},
+			gravity: None,
+			models: Vec::new(),
+			obstacles: Vec::new(),
+			leashes: Vec::new(),
+		}
+	}
+}
+
+// textures share one array of 1024x1024 layers
+fn load_texture(path: &str) -> Result<TextureData, Box<dyn Error>> {
+	eprintln!("INFO: Loading {}", path);
+	let image = image::open(path)
+		.map_err(|e| format!("cannot load {}: {}", path, e))?
+		.into_rgba8();
+	let image = if image.dimensions() == (1024, 1024) {
+		image
+	} else {
+		image::imageops::resize(&image, 1024, 1024, FilterType::Triangle)
+	};
+	Ok(TextureData { image })
+}
+
+// settings done by ImageModelBuilder for images
+fn override_templates(
+	pmodel: &mut PhysicalModel,
+	model: &SceneModel,
+	imass: Option<f32>,
+) {
+	if let Some(imass) = imass {
+		for p in pmodel.particles.iter_mut().filter(|p| p.imass != 0.0) {
+			p.imass = imass;
+		}
+	}
+	for c in pmodel.constraints.iter_mut() {
+		if let ConstraintTemplate::Distance(ct) = c {
+			if let Some(compliance) = model.compliance {
+				ct.compliance = compliance;
+			}
+			if model.break_range.is_some() {
+				ct.break_range = model.break_range;
+			}
+		}
+	}
+}
+
+impl Scene {
+	pub fn load(path: &str) -> Result<Self, Box<dyn Error>> {
+		let mut scene: Self = ron::from_str(&std::fs::read_to_string(path)?)?;
+		let dir = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+		for model in scene.models.iter_mut() {
+			let join = |path: &mut String| {
+				*path = dir.join(&path).to_string_lossy().into();
+			};
+			match &mut model.source {
+				Source::Image(path) | Source::Model(path) => join(path),
+				Source::Svg { path, texture, .. } => {
+					join(path);
+					if let Some(texture) = texture {
+						join(texture);
+					}
+				}
+				_ => {}
+			}
+		}
+		for obstacle in scene.obstacles.iter_mut() {
+			if let SceneObstacle::Image { path, .. } = obstacle {
+				*path = dir.join(&path).to_string_lossy().into();
+			}
+		}
+		Ok(scene)
+	}
+
+	// images stacked upwards in default world
+	pub fn from_images(paths: &[String]) -> Self {
+		let models = paths
+			.iter()
+			.enumerate()
+			.map(|(idx, path)| SceneModel {
+				source: Source::Image(path.clone()),
+				position: [0., -4.0 * (idx + 1) as f32],
+				compliance: None,
+				mass: None,
+				break_range: None,
+			})
+			.collect();
+		Self {
+			models,
+			..Default::default()
+		}
+	}
+
+	pub fn image_paths(&self) -> Vec<String> {
+		self.models
+			.iter()
+			.filter_map(|model| match &model.source {
+				Source::Image(path) => Some(path.clone()),
+				_ => None,
+			})
+			.collect()
+	}
+
+	// settings and models are applied to pworld,
+	// which may be configured before, e.g. with_fracture
+	// fails on a model or texture file that cannot be loaded
+	pub fn build(
+		&self,
+		pworld: PWorld,
+	) -> Result<(PWorld, TextureIndexer, Vec<TextureData>), Box<dyn Error>> {
+		let mut pworld = pworld.with_posbox(self.posbox.clone());
+		if let Some(dt) = self.dt {
+			pworld = pworld.with_dt(dt);
+		}
+		if let Some(ppr) = self.ppr {
+			pworld = pworld.with_ppr(ppr);
+		}
+		if let Some(iteration) = self.iteration {
+			pworld = pworld.with_iteration(iteration);
+		}
+		if let Some(gravity) = self.gravity {
+			pworld = pworld.with_gravity(gravity.into());
+		}
+		for obstacle in self.obstacles.iter() {
+			let obstacle = match obstacle {
+				SceneObstacle::Shape(obstacle) => obstacle.clone(),
+				SceneObstacle::Image {
+					path,
+					origin,
+					cell,
+					surface,
+				} => {
+					let sdf = load_sdf(path, V2::from(*origin), *cell)
+						.map_err(|e| format!("cannot load {}: {}", path, e))?;
+					Obstacle::new(Shape::Sdf(sdf)).with_surface(*surface)
+				}
+			};
+			pworld = pworld.with_obstacle(obstacle);
+		}
+		let mut indexer = TextureIndexer::default();
+		let mut textures = Vec::new();
+		let mut handles = Vec::new();
+		for model in self.models.iter() {
+			let imass = model.mass.map(|m| 1.0 / m);
+			let (pmodel, faces) = match &model.source {
+				Source::Image(path) => {
+					// the builder panics on these
+					let size = image::image_dimensions(path)
+						.map_err(|e| format!("cannot load {}: {}", path, e))?;
+					if size != (1024, 1024) {
+						return Err(format!("{} is not 1024x1024", path).into());
+					}
+					let tid = textures.len() as i32;
+					let mut imbuilder = ImageModelBuilder::new(tid, path);
+					if let Some(compliance) = model.compliance {
+						imbuilder = imbuilder.with_compliance(compliance);
+					}
+					if let Some(imass) = imass {
+						imbuilder = imbuilder.with_imass(imass);
+					}
+					if let Some(range) = model.break_range {
+						imbuilder = imbuilder.with_break_range(range);
+					}
+					imbuilder.compute_cells();
+					imbuilder.expand_cells();
+					let pmodel = imbuilder.build_physical_model();
+					let (texture_data, faces) = imbuilder.finish();
+					textures.push(texture_data);
+					(pmodel, Some(faces))
+				}
+				Source::Model(path) => {
+					let mut file = ModelFile::load(path)
+						.map_err(|e| format!("cannot load {}: {}", path, e))?;
+					let base = textures.len() as i32;
+					file.textures.offset_texture_ids(base);
+					textures.extend(file.textures.textures);
+					let mut pmodel = file.model;
+					override_templates(&mut pmodel, model, imass);
+					(pmodel, Some(file.textures.faces))
+				}
+				Source::Svg {
+					path,
+					scale,
+					spacing,
+					texture,
+				} => {
+					let tid = textures.len() as i32;
+					let mut builder = PolygonModelBuilder::new(tid);
+					if let Some(spacing) = spacing {
+						builder = builder.with_spacing(*spacing);
+					}
+					if let Some(compliance) = model.compliance {
+						builder = builder.with_compliance(compliance);
+					}
+					if let Some(imass) = imass {
+						builder = builder.with_imass(imass);
+					}
+					if let Some(range) = model.break_range {
+						builder = builder.with_break_range(range);
+					}
+					builder
+						.add_svg(path, *scale)
+						.map_err(|e| format!("cannot load {}: {}", path, e))?;
+					let pmodel = builder.build_physical_model();
+					let faces = builder.finish();
+					let faces = match texture {
+						Some(path) => {
+							textures.push(load_texture(path)?);
+							Some(faces)
+						}
+						None => None,
+					};
+					(pmodel, faces)
+				}
+				Source::Fluid(size) => {
+					let mut builder = FluidModelBuilder::default();
+					if let Some(imass) = imass {
+						builder = builder.with_imass(imass);
+					}
+					builder.add_rect(V2::new(0., 0.), *size);
+					(builder.build_physical_model(), None)
+				}
+			};
+			let offset = V2::from(model.position);
+			let positions: Vec<V2> =
+				pmodel.particles.iter().map(|p| p.pos + offset).collect();
+			let handle = pworld.add_model(pmodel, offset);
+			if let Some(faces) = faces {
+				indexer.add_faces(&handle.constraints, faces);
+			}
+			handles.push((handle, positions));
+		}
+		for leash in self.leashes.iter() {
+			let (handle, positions) = match handles.get(leash.model) {
+				Some(x) => x,
+				None => {
+					eprintln!("ERROR: leash model {} is bad", leash.model);
+					continue;
+				}
+			};
+			let (id, pos) = match handle.particles.get(leash.particle) {
+				Some(&id) => (id, positions[leash.particle]),
+				None => {
+					eprintln!(
+						"ERROR: leash particle {} is bad",
+						leash.particle
+					);
+					continue;
+				}
+			};
+			let pos = leash.pos.map(V2::from).unwrap_or(pos);
+			pworld.control_particle(id, pos);
+		}
+		Ok((pworld, indexer, textures))
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_load() {
+		let text = "(
+			ppr: Some(3),
+			posbox: (xmin: -5, xmax: 5, ymin: -10, ymax: 0),
+			models: [(source: Fluid((4, 5)), position: (1, -3), mass: Some(2))],
+			obstacles: [Shape((shape: Segment(([-5, -1], [5, -1]))))],
+			leashes: [
+				(model: 0, particle: 1, pos: Some((1, -5))),
+				(model: 1, particle: 0),
+			],
+		)";
+		let scene: Scene = ron::from_str(text).unwrap();
+		assert_eq!(scene.ppr, Some(3));
+		assert_eq!(scene.dt, None);
+		let (mut pworld, _, textures) = scene.build(PWorld::default()).unwrap();
+		assert!(textures.is_empty());
+		assert_eq!(pworld.posbox().xmax, 5.0);
+		assert_eq!(pworld.obstacles().len(), 1);
+		for _ in 0..20 {
+			pworld.run();
+		}
+		// the bad leash is skipped
+		let model = pworld.pr_model();
+		assert_eq!(model.particles.len(), 20);
+		let pos = V2::from(model.particles[&1].pos);
+		assert!((pos - V2::new(1., -5.)).magnitude() < 0.01);
+	}
+
+	#[test]
+	fn test_build_error() {
+		let text =
+			"(models: [(source: Model(\"missing.psvm\"), position: (0, 0))])";
+		let scene: Scene = ron::from_str(text).unwrap();
+		let e = scene.build(PWorld::default()).err().unwrap();
+		assert!(e.to_string().contains("missing.psvm"));
+	}
+
+	#[test]
+	fn test_image_obstacle() {
+		let name = format!("psva2d_test_sdf_{}.png", std::process::id());
+		let path = std::env::temp_dir().join(name);
+		let path = path.to_str().unwrap();
+		let image =
+			image::ImageBuffer::from_pixel(4, 2, image::Rgba([255u8; 4]));
+		image.save(path).unwrap();
+		let text = format!(
+			"(obstacles: [Image(path: {:?}, origin: (1, -2), cell: 0.5)])",
+			path
+		);
+		let scene: Scene = ron::from_str(&text).unwrap();
+		let result = scene.build(PWorld::default());
+		std::fs::remove_file(path).unwrap();
+		let (pworld, _, _) = result.unwrap();
+		let [min, max] = pworld.obstacles()[0].aabb();
+		assert_eq!((min, max), (V2::new(1., -2.), V2::new(3., -1.)));
+		let e = scene.build(PWorld::default()).err().unwrap();
+		assert!(e.to_string().contains(path));
+	}
+}
@@ -0,0 +1,23 @@
+use std::error::Error;
+
+use xpbd::obstacle::Sdf;
+
+use crate::V2;
+
+// pixels with nonzero alpha are solid, one cell per pixel
+pub fn load_sdf(
+	image_path: &str,
+	origin: V2,
+	cell: f32,
+) -> Result<Sdf, Box<dyn Error>> {
+	eprintln!("INFO: Loading {}", image_path);
+	let image = image::open(image_path)?.into_rgba8();
+	let mask: Vec<Vec<bool>> = (0..image.width())
+		.map(|x| {
+			(0..image.height())
+				.map(|y| image.get_pixel(x, y)[3] != 0)
+				.collect()
+		})
+		.collect();
+	Ok(Sdf::new(&mask, origin, cell))
+}
@@ -0,0 +1,457 @@
+// outlines of path, polygon and polyline elements, in svg user units
+// path commands M L H V C S Q T A Z are supported, absolute and relative
+// curves and arcs are flattened, transform attributes of the element and
+// its parents are applied, styles are ignored
+
+use std::error::Error;
+
+use crate::V2;
+
+// segments of a flattened curve
+const CURVE_STEPS: usize = 8;
+
+// value of attribute name in tag, quoted by " or '
+fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+	let mut rest = tag;
+	while let Some(idx) = rest.find(name) {
+		let before = rest[..idx].chars().last();
+		let after = rest[idx + name.len()..].trim_start();
+		rest = &rest[idx + name.len()..];
+		if !matches!(before, Some(c) if c.is_whitespace()) {
+			continue;
+		}
+		let value = match after.strip_prefix('=') {
+			Some(value) => value.trim_start(),
+			None => continue,
+		};
+		let quote = value.chars().next()?;
+		if quote != '"' && quote != '\'' {
+			continue;
+		}
+		let end = value[1..].find(quote)?;
+		return Some(&value[1..end + 1]);
+	}
+	None
+}
+
+enum Token {
+	Command(char),
+	Number(f32),
+}
+
+fn tokenize(data: &str) -> Result<Vec<Token>, Box<dyn Error>> {
+	let chars: Vec<char> = data.chars().collect();
+	let mut tokens = Vec::new();
+	let mut i = 0;
+	while i < chars.len() {
+		let c = chars[i];
+		if c.is_whitespace() || c == ',' {
+			i += 1;
+		} else if c.is_ascii_alphabetic() && c != 'e' && c != 'E' {
+			tokens.push(Token::Command(c));
+			i += 1;
+		} else {
+			// sign, digits, one dot, exponent
+			let start = i;
+			if chars[i] == '-' || chars[i] == '+' {
+				i += 1;
+			}
+			let mut dot = false;
+			while i < chars.len()
+				&& (chars[i].is_ascii_digit() || (chars[i] == '.' && !dot))
+			{
+				dot |= chars[i] == '.';
+				i += 1;
+			}
+			if i < chars.len() && (chars[i] == 'e' || chars[i] == 'E') {
+				i += 1;
+				if i < chars.len() && (chars[i] == '-' || chars[i] == '+') {
+					i += 1;
+				}
+				while i < chars.len() && chars[i].is_ascii_digit() {
+					i += 1;
+				}
+			}
+			let s: String = chars[start..i].iter().collect();
+			let x = s
+				.parse()
+				.map_err(|_| format!("bad number {:?} in path", s))?;
+			tokens.push(Token::Number(x));
+		}
+	}
+	Ok(tokens)
+}
+
+// a b c d e f of svg matrix(), x' = a x + c y + e, y' = b x + d y + f
+#[derive(Clone, Copy)]
+struct Affine([f32; 6]);
+
+impl Affine {
+	const IDENTITY: Self = Self([1., 0., 0., 1., 0., 0.]);
+
+	// other is applied first
+	fn then(self, other: Self) -> Self {
+		let [a1, b1, c1, d1, e1, f1] = self.0;
+		let [a2, b2, c2, d2, e2, f2] = other.0;
+		Self([
+			a1 * a2 + c1 * b2,
+			b1 * a2 + d1 * b2,
+			a1 * c2 + c1 * d2,
+			b1 * c2 + d1 * d2,
+			a1 * e2 + c1 * f2 + e1,
+			b1 * e2 + d1 * f2 + f1,
+		])
+	}
+
+	fn apply(&self, p: V2) -> V2 {
+		let [a, b, c, d, e, f] = self.0;
+		V2::new(a * p[0] + c * p[1] + e, b * p[0] + d * p[1] + f)
+	}
+}
+
+// list of transform functions, the last one is applied first
+fn parse_transform(data: &str) -> Result<Affine, Box<dyn Error>> {
+	let mut result = Affine::IDENTITY;
+	let mut rest = data;
+	while let Some(open) = rest.find('(') {
+		let name =
+			rest[..open].trim_matches(|c: char| c.is_whitespace() || c == ',');
+		let close = rest[open..]
+			.find(')')
+			.ok_or_else(|| format!("unclosed transform {}", name))?;
+		let mut xs = Vec::new();
+		for token in tokenize(&rest[open + 1..open + close])? {
+			match token {
+				Token::Number(x) => xs.push(x),
+				Token::Command(c) => {
+					return Err(
+						format!("bad character {} in transform", c).into()
+					)
+				}
+			}
+		}
+		rest = &rest[open + close + 1..];
+		let (sin, cos) =
+			xs.first().copied().unwrap_or(0.0).to_radians().sin_cos();
+		let m = match (name, xs.len()) {
+			("matrix", 6) => Affine(xs[..].try_into().unwrap()),
+			("translate", 1 | 2) => {
+				let y = xs.get(1).copied().unwrap_or(0.0);
+				Affine([1., 0., 0., 1., xs[0], y])
+			}
+			("scale", 1 | 2) => {
+				let y = xs.get(1).copied().unwrap_or(xs[0]);
+				Affine([xs[0], 0., 0., y, 0., 0.])
+			}
+			("rotate", 1 | 3) => {
+				let rotate = Affine([cos, sin, -sin, cos, 0., 0.]);
+				match xs[..] {
+					[_, x, y] => Affine([1., 0., 0., 1., x, y])
+						.then(rotate)
+						.then(Affine([1., 0., 0., 1., -x, -y])),
+					_ => rotate,
+				}
+			}
+			("skewX", 1) => Affine([1., 0., sin / cos, 1., 0., 0.]),
+			("skewY", 1) => Affine([1., sin / cos, 0., 1., 0., 0.]),
+			(name, n) => {
+				let e = format!("bad transform {} of {} arguments", name, n);
+				return Err(e.into());
+			}
+		};
+		result = result.then(m);
+	}
+	if !rest.trim().is_empty() {
+		return Err(format!("bad transform {:?}", data).into());
+	}
+	Ok(result)
+}
+
+fn bezier(ps: &[V2], t: f32) -> V2 {
+	let mut ps = ps.to_vec();
+	while ps.len() > 1 {
+		ps = ps.windows(2).map(|w| w[0] + (w[1] - w[0]) * t).collect();
+	}
+	ps[0]
+}
+
+// points of elliptical arc from p0 to p1, p0 excluded
+// args: rx ry x-axis-rotation large-arc-flag sweep-flag
+// center parameterization of svg implementation notes
+fn arc(p0: V2, p1: V2, args: &[f32]) -> Vec<V2> {
+	let (mut rx, mut ry) = (args[0].abs(), args[1].abs());
+	if p0 == p1 {
+		return Vec::new();
+	}
+	if rx == 0.0 || ry == 0.0 {
+		return vec![p1];
+	}
+	let (sin, cos) = args[2].to_radians().sin_cos();
+	let (large, sweep) = (args[3] != 0.0, args[4] != 0.0);
+	// p0 in the frame of the ellipse axes, centered at the chord middle
+	let h = (p0 - p1) / 2.0;
+	let x = cos * h[0] + sin * h[1];
+	let y = -sin * h[0] + cos * h[1];
+	// radii too small to reach are scaled up
+	let lambda = (x / rx).powi(2) + (y / ry).powi(2);
+	if lambda > 1.0 {
+		rx *= lambda.sqrt();
+		ry *= lambda.sqrt();
+	}
+	let (rx2, ry2) = (rx * rx, ry * ry);
+	let den = rx2 * y * y + ry2 * x * x;
+	let mut k = ((rx2 * ry2 - den) / den).max(0.0).sqrt();
+	if large == sweep {
+		k = -k;
+	}
+	let (cx, cy) = (k * rx * y / ry, -k * ry * x / rx);
+	let center =
+		V2::new(cos * cx - sin * cy, sin * cx + cos * cy) + (p0 + p1) / 2.0;
+	let angle = |u: V2, v: V2| (u[0] * v[1] - u[1] * v[0]).atan2(u.dot(&v));
+	let u = V2::new((x - cx) / rx, (y - cy) / ry);
+	let v = V2::new((-x - cx) / rx, (-y - cy) / ry);
+	let theta = angle(V2::new(1., 0.), u);
+	let mut delta = angle(u, v);
+	if !sweep && delta > 0.0 {
+		delta -= std::f32::consts::TAU;
+	} else if sweep && delta < 0.0 {
+		delta += std::f32::consts::TAU;
+	}
+	// CURVE_STEPS per quarter turn
+	let quarters = delta.abs() / std::f32::consts::FRAC_PI_2;
+	let steps = ((quarters * CURVE_STEPS as f32).ceil() as usize).max(1);
+	let mut result: Vec<V2> = (1..steps)
+		.map(|k| {
+			let (s, c) = (theta + delta * k as f32 / steps as f32).sin_cos();
+			let (ex, ey) = (rx * c, ry * s);
+			center + V2::new(cos * ex - sin * ey, sin * ex + cos * ey)
+		})
+		.collect();
+	result.push(p1);
+	result
+}
+
+// closed rings of path data, open subpaths are closed
+pub fn parse_path(data: &str) -> Result<Vec<Vec<V2>>, Box<dyn Error>> {
+	let tokens = tokenize(data)?;
+	let mut rings = Vec::new();
+	let mut ring: Vec<V2> = Vec::new();
+	let mut pos = V2::zeros();
+	// last control point of the previous segment, and its command
+	let mut ctrl = V2::zeros();
+	let mut prev = 'M';
+	let mut command = None;
+	let mut idx = 0;
+	while idx < tokens.len() {
+		if let Token::Command(c) = tokens[idx] {
+			command = Some(c);
+			idx += 1;
+			if c == 'z' || c == 'Z' {
+				if let Some(&start) = ring.first() {
+					pos = start;
+				}
+				rings.push(std::mem::take(&mut ring));
+				prev = 'Z';
+				continue;
+			}
+		}
+		let c = command.ok_or("path data does not start with a command")?;
+		let upper = c.to_ascii_uppercase();
+		let argc = match upper {
+			'M' | 'L' | 'T' => 2,
+			'H' | 'V' => 1,
+			'C' => 6,
+			'S' | 'Q' => 4,
+			'A' => 7,
+			'Z' => return Err("path data after z is not a command".into()),
+			_ => return Err(format!("unsupported path command {}", c).into()),
+		};
+		let mut args = Vec::new();
+		for _ in 0..argc {
+			match tokens.get(idx) {
+				Some(Token::Number(x)) => args.push(*x),
+				_ => return Err(format!("missing argument of {}", c).into()),
+			}
+			idx += 1;
+		}
+		let base = if c.is_ascii_lowercase() {
+			pos
+		} else {
+			V2::zeros()
+		};
+		let point = |i: usize| base + V2::new(args[i], args[i + 1]);
+		match upper {
+			'M' => {
+				if !ring.is_empty() {
+					rings.push(std::mem::take(&mut ring));
+				}
+				pos = point(0);
+				ring.push(pos);
+				// following pairs are lines
+				command = Some(if c == 'm' { 'l' } else { 'L' });
+			}
+			'L' => {
+				pos = point(0);
+				ring.push(pos);
+			}
+			'H' => {
+				pos[0] = base[0] + args[0];
+				ring.push(pos);
+			}
+			'V' => {
+				pos[1] = base[1] + args[0];
+				ring.push(pos);
+			}
+			'A' => {
+				let end = point(5);
+				ring.extend(arc(pos, end, &args));
+				pos = end;
+			}
+			_ => {
+				let mut ps = vec![pos];
+				// first control point is the last one of the previous
+				// curve mirrored, or the current point
+				let smooth = match upper {
+					'S' => Some(matches!(prev, 'C' | 'S')),
+					'T' => Some(matches!(prev, 'Q' | 'T')),
+					_ => None,
+				};
+				match smooth {
+					Some(true) => ps.push(pos * 2.0 - ctrl),
+					Some(false) => ps.push(pos),
+					None => {}
+				}
+				ps.extend((0..argc).step_by(2).map(point));
+				for k in 1..=CURVE_STEPS {
+					ring.push(bezier(&ps, k as f32 / CURVE_STEPS as f32));
+				}
+				ctrl = ps[ps.len() - 2];
+				pos = *ps.last().unwrap();
+			}
+		}
+		prev = upper;
+	}
+	rings.push(ring);
+	rings.retain(|ring| ring.len() >= 3);
+	Ok(rings)
+}
+
+fn parse_points(data: &str) -> Result<Vec<V2>, Box<dyn Error>> {
+	let mut xs = Vec::new();
+	for token in tokenize(data)? {
+		match token {
+			Token::Number(x) => xs.push(x),
+			Token::Command(c) => {
+				return Err(format!("bad character {} in points", c).into())
+			}
+		}
+	}
+	Ok(xs.chunks_exact(2).map(|x| V2::new(x[0], x[1])).collect())
+}
+
+pub fn parse_svg(text: &str) -> Result<Vec<Vec<V2>>, Box<dyn Error>> {
+	let mut outlines = Vec::new();
+	// transforms of open elements
+	let mut stack = vec![Affine::IDENTITY];
+	for tag in text.split('<').skip(1) {
+		let tag = &tag[..tag.find('>').unwrap_or(tag.len())];
+		if tag.starts_with('/') {
+			if stack.len() > 1 {
+				stack.pop();
+			}
+			continue;
+		}
+		// declarations and comments
+		if tag.starts_with('?') || tag.starts_with('!') {
+			continue;
+		}
+		let mut transform = *stack.last().unwrap();
+		if let Some(data) = attribute(tag, "transform") {
+			transform = transform.then(parse_transform(data)?);
+		}
+		let name = tag.split_whitespace().next().unwrap_or("");
+		let mut rings = Vec::new();
+		match name {
+			"path" => {
+				if let Some(d) = attribute(tag, "d") {
+					rings.extend(parse_path(d)?);
+				}
+			}
+			"polygon" | "polyline" => {
+				if let Some(points) = attribute(tag, "points") {
+					rings.push(parse_points(points)?);
+				}
+			}
+			_ => {}
+		}
+		for ring in rings.into_iter() {
+			outlines
+				.push(ring.into_iter().map(|p| transform.apply(p)).collect());
+		}
+		if !tag.ends_with('/') {
+			stack.push(transform);
+		}
+	}
+	outlines.retain(|outline: &Vec<V2>| outline.len() >= 3);
+	Ok(outlines)
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_parse() {
+		let text = r#"<svg viewBox="0 0 10 10">
+			<path id="a" d="M1,1 h8 v8 H1z m2 2 l0 4 4-.5.5-3.5 Q 5 2 3 3 Z"/>
+			<polygon points="0 0, 2 0, 2 2"/>
+		</svg>"#;
+		let outlines = parse_svg(text).unwrap();
+		assert_eq!(outlines.len(), 3);
+		assert_eq!(outlines[0][2], V2::new(9., 9.));
+		assert_eq!(outlines[0].len(), 4);
+		// relative to the start of the closed subpath
+		assert_eq!(outlines[1][0], V2::new(3., 3.));
+		assert_eq!(outlines[1][3], V2::new(7.5, 3.));
+		assert_eq!(outlines[1].len(), 4 + CURVE_STEPS);
+		assert_eq!(outlines[2][1], V2::new(2., 0.));
+		assert!(parse_path("M0 0 B 1 1").is_err());
+	}
+
+	fn close(a: V2, b: V2) -> bool {
+		(a - b).magnitude() < 1e-4
+	}
+
+	#[test]
+	fn test_smooth_arc() {
+		// mirrored control points continue the curve
+		let rings = parse_path("M0 0 C0 1 1 1 1 0 S2 -1 2 0 Z").unwrap();
+		assert!(close(rings[0][CURVE_STEPS + 4], V2::new(1.5, -0.75)));
+		let rings = parse_path("M0 0 Q1 1 2 0 t2 0 Z").unwrap();
+		assert!(close(rings[0][CURVE_STEPS + 4], V2::new(3., -0.5)));
+		// half circle over the chord from (0, 0) to (2, 0)
+		let rings = parse_path("M0 0 A1 1 0 0 1 2 0 Z").unwrap();
+		let center = V2::new(1., 0.);
+		for &p in rings[0].iter() {
+			assert!(((p - center).magnitude() - 1.0).abs() < 1e-4);
+		}
+		assert!(rings[0].iter().any(|&p| close(p, V2::new(1., -1.))));
+	}
+
+	#[test]
+	fn test_transform() {
+		let text = r#"<svg>
+			<g transform="translate(10, 0)">
+				<polygon transform="scale(2)" points="0 0, 1 0, 1 1"/>
+			</g>
+			<path transform="rotate(90 1 1)" d="M1 0 L2 0 L2 1 Z"></path>
+			<polygon points="0 0, 1 0, 1 1"/>
+		</svg>"#;
+		let outlines = parse_svg(text).unwrap();
+		assert_eq!(outlines.len(), 3);
+		assert_eq!(outlines[0][2], V2::new(12., 2.));
+		assert!(close(outlines[1][1], V2::new(2., 2.)));
+		assert_eq!(outlines[2][2], V2::new(1., 1.));
+		assert!(parse_transform("rotate(1 2)").is_err());
+	}
+}
@@ -4,19 +4,35 @@ use std::rc::Rc;
 
 use crate::face::{Face, FaceGroup};
 use crate::render_model::RenderModel;
-use protocol::pr_model::PrModel;
+use crate::V2;
+use protocol::pr_model::{PrConstraintType, PrFace, PrModel};
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct FaceInfo {
 	pub texture_id: i32,
-	pub uvid: [usize; 3],
+	pub uv: [V2; 3],
 }
 
 impl Default for FaceInfo {
 	fn default() -> Self {
 		Self {
 			texture_id: -1,
-			uvid: [0; 3],
+			uv: [V2::zeros(); 3],
+		}
+	}
+}
+
+impl FaceInfo {
+	// face info of a face inside this face
+	pub fn interpolate(&self, bary: &[[f32; 3]; 3]) -> Self {
+		let mut uv = [V2::zeros(); 3];
+		for (i, w) in bary.iter().enumerate() {
+			uv[i] = w[0] * self.uv[0] + w[1] * self.uv[1] + w[2] * self.uv[2];
+		}
+		Self {
+			texture_id: self.texture_id,
+			uv,
 		}
 	}
 }
@@ -38,38 +54,107 @@ impl TextureIndexer {
 		for (id, particle) in &pr_model.particles {
 			result.vs.insert(*id, particle.pos);
 		}
+		let derived: HashMap<i32, &PrFace> =
+			pr_model.faces.iter().map(|x| (x.id, x)).collect();
 		for constraint in pr_model.constraints.iter() {
-			if constraint.particles.len() == 3 {
-				let texind = if let Some(ind) =
-					self.texture_map.get(&constraint.id).cloned()
-				{
-					ind
-				} else {
-					eprintln!("Indexer constraint {} not found", constraint.id);
-					FaceInfo::default()
-				};
-				let face = Face {
-					vid: constraint.particles.clone().try_into().unwrap(),
-					uvid: texind.uvid,
-				};
-				let e = result
-					.face_groups
-					.entry(texind.texture_id)
-					.or_insert_with(FaceGroup::default);
-				e.faces.push(face);
+			if constraint.ty != PrConstraintType::Face {
+				continue;
 			}
+			let texind = match self.face_info(constraint.id, &derived) {
+				Some(ind) => ind,
+				None => {
+					eprintln!("WARN: indexer face {} not found", constraint.id);
+					continue;
+				}
+			};
+			let face = Face {
+				vid: constraint.particles.clone().try_into().unwrap(),
+				uv: texind.uv.map(|x| x.into()),
+			};
+			let e = result
+				.face_groups
+				.entry(texind.texture_id)
+				.or_insert_with(FaceGroup::default);
+			e.faces.push(face);
 		}
 		result
 	}
 
+	// texture of a model face, or of the root face of a remeshed one
+	fn face_info(
+		&self,
+		id: i32,
+		derived: &HashMap<i32, &PrFace>,
+	) -> Option<FaceInfo> {
+		if let Some(ind) = self.texture_map.get(&id) {
+			return Some(ind.clone());
+		}
+		let face = derived.get(&id)?;
+		Some(self.texture_map.get(&face.root)?.interpolate(&face.bary))
+	}
+
+	// faces are keyed by model constraint idx
+	// constraints: model constraint idx -> constraint id(ModelHandle)
 	pub fn add_faces(
 		&mut self,
-		cids: Vec<i32>,
+		constraints: &[i32],
 		faces: HashMap<usize, FaceInfo>,
 	) {
 		for (idx, face_info) in faces.into_iter() {
-			let ret = self.texture_map.insert(cids[idx], face_info);
+			let cid = constraints[idx];
+			let ret = self.texture_map.insert(cid, face_info);
 			assert!(ret.is_none());
 		}
 	}
 }
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::polygon_model::PolygonModelBuilder;
+	use xpbd::pworld::PWorld;
+
+	fn face_count(indexer: &TextureIndexer, pworld: &PWorld) -> usize {
+		let render_model = indexer.compile_model(&pworld.pr_model());
+		render_model
+			.face_groups
+			.values()
+			.map(|g| g.faces.len())
+			.sum()
+	}
+
+	// faces depending on a removed edge are removed and not rendered
+	#[test]
+	fn test_remove_edge() {
+		let mut builder = PolygonModelBuilder::new(0).with_spacing(0.5);
+		builder.add_outline(vec![
+			V2::new(0., 0.),
+			V2::new(1., 0.),
+			V2::new(1., 1.),
+			V2::new(0., 1.),
+		]);
+		let model = builder.build_physical_model();
+		let deps = model.dependencies.clone();
+		let mut pworld = PWorld::default();
+		let handle = pworld.add_model(model, V2::zeros());
+		let mut indexer = TextureIndexer::default();
+		indexer.add_faces(&handle.constraints, builder.finish());
+		let n = face_count(&indexer, &pworld);
+		assert_eq!(n, handle.faces.len());
+		let edge = deps[0][0];
+		let mut faces: Vec<i32> = deps
+			.iter()
+			.filter(|dep| dep[0] == edge)
+			.map(|dep| handle.constraints[dep[1]])
+			.collect();
+		let cid = handle.constraints[edge];
+		let mut removed = pworld.remove_constraints(vec![cid]);
+		removed.sort_unstable();
+		faces.push(cid);
+		faces.sort_unstable();
+		assert_eq!(removed, faces);
+		let cs = pworld.pr_model().constraints;
+		assert!(cs.iter().all(|c| !removed.contains(&c.id)));
+		assert_eq!(face_count(&indexer, &pworld), n + 1 - removed.len());
+	}
+}
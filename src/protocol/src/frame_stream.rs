@@ -0,0 +1,246 @@
+// frame_stream: PrModel of each frame in a compact binary stream
+// little endian, header then frames:
+// frame: u32 frame index
+//   u32 particle count, each: u32 id, f32 x, f32 y, u32 model, u8 fluid
+//   u32 constraint count, each: i32 id, u8 type, u32 n, n * u32 particle id
+//     type: 0 other, 1 face, 2 angle
+//   u32 face count, each: i32 id, i32 root, 9 * f32 bary
+
+use std::io::{ErrorKind, Read, Result, Write};
+
+use crate::pr_model::{
+	PrConstraint, PrConstraintType, PrFace, PrModel, PrParticle,
+};
+
+const MAGIC: &[u8; 4] = b"PSVF";
+const VERSION: u32 = 3;
+
+pub struct FrameWriter<W: Write> {
+	w: W,
+}
+
+impl<W: Write> FrameWriter<W> {
+	pub fn new(mut w: W) -> Result<Self> {
+		w.write_all(MAGIC)?;
+		w.write_all(&VERSION.to_le_bytes())?;
+		Ok(Self { w })
+	}
+
+	// particles are written in id order
+	pub fn write_frame(&mut self, frame: usize, model: &PrModel) -> Result<()> {
+		let w = &mut self.w;
+		w.write_all(&(frame as u32).to_le_bytes())?;
+		let mut ids: Vec<usize> = model.particles.keys().copied().collect();
+		ids.sort_unstable();
+		w.write_all(&(ids.len() as u32).to_le_bytes())?;
+		for id in ids.into_iter() {
+			let p = &model.particles[&id];
+			w.write_all(&(id as u32).to_le_bytes())?;
+			w.write_all(&p.pos[0].to_le_bytes())?;
+			w.write_all(&p.pos[1].to_le_bytes())?;
+			w.write_all(&(p.model as u32).to_le_bytes())?;
+			w.write_all(&[p.fluid as u8])?;
+		}
+		w.write_all(&(model.constraints.len() as u32).to_le_bytes())?;
+		for c in model.constraints.iter() {
+			w.write_all(&c.id.to_le_bytes())?;
+			w.write_all(&[ty_code(c.ty)])?;
+			// shape matching covers a whole model
+			w.write_all(&(c.particles.len() as u32).to_le_bytes())?;
+			for &id in c.particles.iter() {
+				w.write_all(&(id as u32).to_le_bytes())?;
+			}
+		}
+		w.write_all(&(model.faces.len() as u32).to_le_bytes())?;
+		for face in model.faces.iter() {
+			w.write_all(&face.id.to_le_bytes())?;
+			w.write_all(&face.root.to_le_bytes())?;
+			for x in face.bary.iter().flatten() {
+				w.write_all(&x.to_le_bytes())?;
+			}
+		}
+		Ok(())
+	}
+
+	pub fn into_inner(self) -> W {
+		self.w
+	}
+}
+
+pub struct FrameReader<R: Read> {
+	r: R,
+}
+
+fn ty_code(ty: PrConstraintType) -> u8 {
+	match ty {
+		PrConstraintType::Other => 0,
+		PrConstraintType::Face => 1,
+		PrConstraintType::Angle => 2,
+	}
+}
+
+fn read_ty(r: &mut impl Read) -> Result<PrConstraintType> {
+	match read_u8(r)? {
+		0 => Ok(PrConstraintType::Other),
+		1 => Ok(PrConstraintType::Face),
+		2 => Ok(PrConstraintType::Angle),
+		x => Err(std::io::Error::new(
+			ErrorKind::InvalidData,
+			format!("unknown constraint type {}", x),
+		)),
+	}
+}
+
+fn read_u8(r: &mut impl Read) -> Result<u8> {
+	let mut buf = [0; 1];
+	r.read_exact(&mut buf)?;
+	Ok(buf[0])
+}
+
+fn read_u32(r: &mut impl Read) -> Result<u32> {
+	let mut buf = [0; 4];
+	r.read_exact(&mut buf)?;
+	Ok(u32::from_le_bytes(buf))
+}
+
+fn read_i32(r: &mut impl Read) -> Result<i32> {
+	Ok(read_u32(r)? as i32)
+}
+
+fn read_f32(r: &mut impl Read) -> Result<f32> {
+	Ok(f32::from_bits(read_u32(r)?))
+}
+
+impl<R: Read> FrameReader<R> {
+	pub fn new(mut r: R) -> Result<Self> {
+		let mut magic = [0; 4];
+		r.read_exact(&mut magic)?;
+		if &magic != MAGIC {
+			return Err(std::io::Error::new(
+				ErrorKind::InvalidData,
+				"not a frame stream",
+			));
+		}
+		let version = read_u32(&mut r)?;
+		if version != VERSION {
+			return Err(std::io::Error::new(
+				ErrorKind::InvalidData,
+				format!("frame stream version {} is not supported", version),
+			));
+		}
+		Ok(Self { r })
+	}
+
+	// None at the end of stream
+	pub fn read_frame(&mut self) -> Result<Option<(usize, PrModel)>> {
+		let r = &mut self.r;
+		let frame = match read_u32(r) {
+			Ok(frame) => frame as usize,
+			Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+			Err(e) => return Err(e),
+		};
+		let mut model = PrModel::default();
+		for _ in 0..read_u32(r)? {
+			let id = read_u32(r)? as usize;
+			let pos = [read_f32(r)?, read_f32(r)?];
+			let model_group = read_u32(r)? as usize;
+			let fluid = read_u8(r)? != 0;
+			let p = PrParticle {
+				pos,
+				fluid,
+				model: model_group,
+			};
+			model.particles.insert(id, p);
+		}
+		for _ in 0..read_u32(r)? {
+			let id = read_i32(r)?;
+			let ty = read_ty(r)?;
+			let n = read_u32(r)?;
+			let particles = (0..n)
+				.map(|_| Ok(read_u32(r)? as usize))
+				.collect::<Result<_>>()?;
+			model.constraints.push(PrConstraint { id, ty, particles });
+		}
+		for _ in 0..read_u32(r)? {
+			let id = read_i32(r)?;
+			let root = read_i32(r)?;
+			let mut bary = [[0.0; 3]; 3];
+			for x in bary.iter_mut().flatten() {
+				*x = read_f32(r)?;
+			}
+			model.faces.push(PrFace { id, root, bary });
+		}
+		Ok(Some((frame, model)))
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_round_trip() {
+		let mut model = PrModel::default();
+		for id in [3, 0, 7] {
+			let p = PrParticle {
+				pos: [id as f32, -1.5],
+				fluid: id == 7,
+				model: 1,
+			};
+			model.particles.insert(id, p);
+		}
+		model.constraints.push(PrConstraint {
+			id: 4,
+			ty: PrConstraintType::Angle,
+			particles: vec![0, 3, 7],
+		});
+		model.faces.push(PrFace {
+			id: 4,
+			root: 2,
+			bary: [[0.5, 0.5, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+		});
+		let mut writer = FrameWriter::new(Vec::new()).unwrap();
+		writer.write_frame(0, &model).unwrap();
+		writer.write_frame(1, &PrModel::default()).unwrap();
+		let data = writer.into_inner();
+		let mut reader = FrameReader::new(&data[..]).unwrap();
+		let (frame, m) = reader.read_frame().unwrap().unwrap();
+		assert_eq!(frame, 0);
+		assert_eq!(m.particles.len(), 3);
+		assert_eq!(m.particles[&7].pos, [7.0, -1.5]);
+		assert!(m.particles[&7].fluid);
+		assert_eq!(m.constraints[0].ty, PrConstraintType::Angle);
+		assert_eq!(m.constraints[0].particles, vec![0, 3, 7]);
+		assert_eq!(m.faces[0].bary[0], [0.5, 0.5, 0.0]);
+		let (frame, m) = reader.read_frame().unwrap().unwrap();
+		assert_eq!(frame, 1);
+		assert!(m.particles.is_empty());
+		assert!(reader.read_frame().unwrap().is_none());
+	}
+
+	// particle count of a constraint does not fit in a byte
+	#[test]
+	fn test_large_constraint() {
+		let mut model = PrModel::default();
+		model.constraints.push(PrConstraint {
+			id: 0,
+			ty: PrConstraintType::Other,
+			particles: (0..1000).collect(),
+		});
+		model.faces.push(PrFace {
+			id: 1,
+			root: 1,
+			bary: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+		});
+		let mut writer = FrameWriter::new(Vec::new()).unwrap();
+		writer.write_frame(2, &model).unwrap();
+		let data = writer.into_inner();
+		let mut reader = FrameReader::new(&data[..]).unwrap();
+		let (frame, m) = reader.read_frame().unwrap().unwrap();
+		assert_eq!(frame, 2);
+		assert_eq!(m.constraints[0].particles.len(), 1000);
+		assert_eq!(m.constraints[0].particles[999], 999);
+		assert_eq!(m.faces[0].root, 1);
+		assert!(reader.read_frame().unwrap().is_none());
+	}
+}
@@ -1,3 +1,4 @@
+pub mod frame_stream;
 pub mod pr_model;
 pub mod user_event;
 pub mod view;
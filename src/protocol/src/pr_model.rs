@@ -4,17 +4,39 @@ use std::collections::HashMap;
 
 pub struct PrParticle {
 	pub pos: [f32; 2],
+	pub fluid: bool,
+	// model group, 0 for none
+	pub model: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PrConstraintType {
+	// triangle of a model, textured by its id
+	Face,
+	// joint, drawn as the two arms from the middle particle
+	Angle,
+	Other,
 }
 
 pub struct PrConstraint {
 	pub id: i32,
+	pub ty: PrConstraintType,
 	pub particles: Vec<usize>,
 }
 
+// face constraint created by remeshing
+// corners in barycentric coordinates of root face corners
+pub struct PrFace {
+	pub id: i32,
+	pub root: i32,
+	pub bary: [[f32; 3]; 3],
+}
+
 #[derive(Default)]
 pub struct PrModel {
 	pub particles: HashMap<usize, PrParticle>,
 	pub constraints: Vec<PrConstraint>,
+	pub faces: Vec<PrFace>,
 }
 
 impl std::fmt::Debug for PrModel {
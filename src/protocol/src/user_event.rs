@@ -3,6 +3,14 @@ use crate::pr_model::PrModel;
 #[derive(Debug)]
 pub enum UserEvent {
 	Update(PrModel, UpdateInfo),
+	// reply to ControllerMessage::AddModel
+	ModelAdded {
+		// model group
+		group: usize,
+		// model constraint idx -> constraint id
+		constraints: Vec<i32>,
+		payload: Vec<u8>,
+	},
 }
 
 #[derive(Debug)]
@@ -10,4 +18,8 @@ pub struct UpdateInfo {
 	pub load: f32,
 	pub particle_len: usize,
 	pub constraint_len: Vec<usize>,
+	// frames done by the world
+	pub frame: usize,
+	// first and last frame that can be rewound to
+	pub history: Option<[usize; 2]>,
 }
@@ -0,0 +1,75 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use material::scene::Scene;
+use protocol::frame_stream::FrameWriter;
+use stpw::Timer;
+use xpbd::pworld::PWorld;
+
+// headless [-n frames] [-o output] (scene.ron | image...)
+// same world as images, frames are written without a window
+fn main() {
+	let mut iter = std::env::args();
+	iter.next();
+	let mut paths: Vec<String> = vec![];
+	let mut frames = 100;
+	let mut output = String::from("frames.bin");
+	while let Some(arg) = iter.next() {
+		match arg.as_str() {
+			"-n" => {
+				frames = iter
+					.next()
+					.and_then(|x| x.parse().ok())
+					.expect("-n needs frame count")
+			}
+			"-o" => output = iter.next().expect("-o needs output path"),
+			_ => paths.push(arg),
+		}
+	}
+	let scene = match paths.first() {
+		Some(path) if path.ends_with(".ron") => match Scene::load(path) {
+			Ok(scene) => scene,
+			Err(e) => {
+				eprintln!("ERROR: scene {} failed: {}", path, e);
+				std::process::exit(1);
+			}
+		},
+		_ => Scene::from_images(&paths),
+	};
+	let (mut pworld, _, _) = match scene.build(PWorld::default()) {
+		Ok(x) => x,
+		Err(e) => {
+			eprintln!("ERROR: {}", e);
+			std::process::exit(1);
+		}
+	};
+	let file = BufWriter::new(File::create(&output).unwrap());
+	let mut writer = FrameWriter::new(file).unwrap();
+	let mut write = |pworld: &PWorld| {
+		writer
+			.write_frame(pworld.frame(), &pworld.pr_model())
+			.unwrap()
+	};
+	write(&pworld);
+	let mut times = Vec::new();
+	for _ in 0..frames {
+		let mut timer = Timer::default();
+		pworld.run();
+		let (dt, _) = timer.lap();
+		eprintln!("INFO: frame {}: {:.2} ms", pworld.frame(), dt * 1e3);
+		times.push(dt);
+		write(&pworld);
+	}
+	writer.into_inner().flush().unwrap();
+	if !times.is_empty() {
+		let total: f32 = times.iter().sum();
+		let max = times.iter().copied().fold(0f32, f32::max);
+		println!(
+			"{} frames written to {}, {:.2} ms/frame, max {:.2} ms",
+			times.len(),
+			output,
+			total / times.len() as f32 * 1e3,
+			max * 1e3,
+		);
+	}
+}
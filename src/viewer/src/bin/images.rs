@@ -1,25 +1,72 @@
-use material::face::TextureData;
 use material::image_model::ImageModelBuilder;
-use material::texture_indexer::TextureIndexer;
-use xpbd::V2;
+use material::model_file::ModelFile;
+use material::scene::Scene;
+use xpbd::pworld::PWorld;
+use xpbd::recorder::{Recorder, Replay};
 
+// images [--record log | --replay log] (scene.ron | image...)
+// images --save-model file image: write model file of image, no window
+// a replay needs the scene of the recording for textures,
+// spawned models have theirs in the log
 fn main() {
 	let mut iter = std::env::args();
 	iter.next();
-	let mut textures: Vec<TextureData> = vec![];
-	let mut indexer = TextureIndexer::default();
-	let mut pworld = xpbd::pworld::PWorld::default().with_paused();
-	let mut y = -4.0;
-	for (tid, image_path) in iter.enumerate() {
-		let mut imbuilder = ImageModelBuilder::new(tid as i32, &image_path);
-		imbuilder.compute_cells();
-		imbuilder.expand_cells();
-		let pmodel = imbuilder.build_physical_model();
-		let cids = pworld.add_model(pmodel.clone(), V2::new(-0., y));
-		y -= 4.0;
-		let (texture_data, faces) = imbuilder.finish();
-		indexer.add_faces(cids, faces);
-		textures.push(texture_data);
+	let mut paths: Vec<String> = vec![];
+	let mut record = None;
+	let mut replay = None;
+	let mut save_model = None;
+	while let Some(arg) = iter.next() {
+		match arg.as_str() {
+			"--record" => record = iter.next(),
+			"--replay" => replay = iter.next(),
+			"--save-model" => save_model = iter.next(),
+			_ => paths.push(arg),
+		}
 	}
-	viewer::viewer::Viewer::new(pworld, indexer.into_ref(), textures).run();
+	if let Some(path) = save_model {
+		let image = match paths.first() {
+			Some(image) => image,
+			None => {
+				eprintln!("ERROR: --save-model needs an image");
+				std::process::exit(1);
+			}
+		};
+		let mut builder = ImageModelBuilder::new(0, image);
+		builder.compute_cells();
+		builder.expand_cells();
+		if let Err(e) = ModelFile::from_image_builder(builder).save(&path) {
+			eprintln!("ERROR: save {} failed: {}", path, e);
+			std::process::exit(1);
+		}
+		eprintln!("INFO: saved {}", path);
+		return;
+	}
+	let scene = match paths.first() {
+		Some(path) if path.ends_with(".ron") => match Scene::load(path) {
+			Ok(scene) => scene,
+			Err(e) => {
+				eprintln!("ERROR: scene {} failed: {}", path, e);
+				std::process::exit(1);
+			}
+		},
+		_ => Scene::from_images(&paths),
+	};
+	let pworld = PWorld::default().with_paused().with_history(10, 200);
+	let (mut pworld, indexer, textures) = match scene.build(pworld) {
+		Ok(x) => x,
+		Err(e) => {
+			eprintln!("ERROR: {}", e);
+			std::process::exit(1);
+		}
+	};
+	if let Some(path) = replay {
+		// constraint ids of the models match the recorded world
+		let replay = Replay::load(&path).unwrap();
+		pworld = PWorld::from_replay(replay).unwrap().with_history(10, 200);
+	} else if let Some(path) = record {
+		pworld = pworld.with_recorder(Recorder::create(&path).unwrap());
+	}
+	viewer::viewer::Viewer::new(pworld, indexer.into_ref(), textures)
+		.with_spawn_images(scene.image_paths())
+		.run();
 }
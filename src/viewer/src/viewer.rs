@@ -10,6 +10,8 @@ use winit::event_loop::{ControlFlow, EventLoop, EventLoopProxy};
 
 use crate::keycode::key2byte;
 use material::face::TextureData;
+use material::image_model::ImageModelBuilder;
+use material::model_file::ModelTextures;
 use material::texture_indexer::TextureIndexerRef;
 use protocol::pr_model::PrModel;
 use protocol::view::View;
@@ -21,6 +23,8 @@ use xpbd::controller_message::ControllerMessage;
 use xpbd::posbox::Posbox;
 use xpbd::pworld::PWorld;
 
+const SNAPSHOT_PATH: &str = "snapshot.bin";
+
 pub struct Viewer {
 	view: View,
 	pworld: Option<PWorld>,
@@ -32,47 +36,62 @@ pub struct Viewer {
 	last_model: Option<PrModel>,
 	input_buffer: Vec<u8>,
 	controller: Option<Sender<ControllerMessage>>,
+	// faces of removed constraints are kept, they come back when a
+	// snapshot is loaded, and their ids are not reused by the world
+	indexer: TextureIndexerRef,
+	// screen position of mouse
+	cursor: V2,
+	// images spawned at runtime, in turn
+	spawn_images: Vec<String>,
+	spawn_count: usize,
+	// texture id for next added texture
+	next_texture: usize,
+	// frame index of last update
+	frame: usize,
 }
 
 impl Viewer {
 	pub fn new(
-		mut pworld: PWorld,
+		pworld: PWorld,
 		indexer: TextureIndexerRef,
 		textures: Vec<TextureData>,
 	) -> Self {
 		let window_size = [800u32, 600];
-		let xmin = -15.0;
-		let xmax = 15.0;
-		let ymin = -30.0;
-		let ymax = 0.;
-		let posbox = Posbox {
+		let Posbox {
 			xmin,
 			xmax,
 			ymin,
 			ymax,
-		};
-		pworld = pworld.with_posbox(posbox);
+		} = *pworld.posbox();
 		let event_loop: EventLoop<UserEvent> = EventLoop::with_user_event();
+		let next_texture = textures.len();
 		let mut vkr =
-			VkRender::new(&event_loop, window_size, textures, indexer);
-		vkr.set_primitives(
-			vec![
-				[xmin, ymin],
-				[xmin, ymax],
-				[xmin, ymax],
-				[xmax, ymax],
-				[xmax, ymax],
-				[xmax, ymin],
-				[xmax, ymin],
-				[xmin, ymin],
-			]
-			.into_iter()
-			.map(|pos| VertexWf {
-				color: [1.0, 1.0, 0.0, 1.0],
-				pos,
-			})
-			.collect(),
-		);
+			VkRender::new(&event_loop, window_size, textures, indexer.clone());
+		let mut primitives: Vec<VertexWf> = vec![
+			[xmin, ymin],
+			[xmin, ymax],
+			[xmin, ymax],
+			[xmax, ymax],
+			[xmax, ymax],
+			[xmax, ymin],
+			[xmax, ymin],
+			[xmin, ymin],
+		]
+		.into_iter()
+		.map(|pos| VertexWf {
+			color: [1.0, 1.0, 0.0, 1.0],
+			pos,
+		})
+		.collect();
+		for obstacle in pworld.obstacles() {
+			for line in obstacle.lines() {
+				primitives.extend(line.iter().map(|pos| VertexWf {
+					color: [0.0, 1.0, 1.0, 1.0],
+					pos: [pos[0], pos[1]],
+				}));
+			}
+		}
+		vkr.set_primitives(primitives);
 
 		Self {
 			view: View::default(),
@@ -85,20 +104,57 @@ impl Viewer {
 			last_model: None,
 			input_buffer: Vec::new(),
 			controller: None,
+			indexer,
+			cursor: V2::new(0.0, 0.0),
+			spawn_images: Vec::new(),
+			spawn_count: 0,
+			next_texture,
+			frame: 0,
 		}
 	}
 
-	fn select_particle(&mut self, c: V2) {
-		let mut min_dist = f32::INFINITY;
-		let mut min_id = 0;
-		let c = self.view.s2w(c);
-		let pr_model = match self.last_model.as_ref() {
-			None => {
-				self.particle_id = None;
+	pub fn with_spawn_images(mut self, paths: Vec<String>) -> Self {
+		self.spawn_images = paths;
+		self
+	}
+
+	// add model of next spawn image at cursor
+	// texture is uploaded when the model is added,
+	// it is sent along so a recording keeps it
+	fn spawn_model(&mut self) {
+		if self.spawn_images.is_empty() {
+			eprintln!("WARN: no image to spawn");
+			return;
+		}
+		let idx = self.spawn_count % self.spawn_images.len();
+		let path = self.spawn_images[idx].clone();
+		self.spawn_count += 1;
+		let mut imbuilder = ImageModelBuilder::new(0, &path);
+		imbuilder.compute_cells();
+		imbuilder.expand_cells();
+		let pmodel = imbuilder.build_physical_model();
+		let (texture, faces) = imbuilder.finish();
+		let textures = ModelTextures {
+			faces,
+			textures: vec![texture],
+		};
+		let payload = match textures.encode() {
+			Ok(payload) => payload,
+			Err(e) => {
+				eprintln!("ERROR: encode texture of {} failed: {}", path, e);
 				return;
 			}
-			Some(m) => m,
 		};
+		let offset = self.view.s2w(self.cursor);
+		self.send(ControllerMessage::AddModel(pmodel, offset.into(), payload));
+	}
+
+	// particle near screen position c
+	fn nearest_particle(&self, c: V2) -> Option<usize> {
+		let mut min_dist = f32::INFINITY;
+		let mut min_id = 0;
+		let c = self.view.s2w(c);
+		let pr_model = self.last_model.as_ref()?;
 		for (id, particle) in &pr_model.particles {
 			let pos: V2 = particle.pos.into();
 			let dist = (c - pos).magnitude();
@@ -107,11 +163,15 @@ impl Viewer {
 				min_dist = dist;
 			}
 		}
-		self.particle_id = if min_dist < 0.005 * self.view.get_zoom() {
+		if min_dist < 0.005 * self.view.get_zoom() {
 			Some(min_id)
 		} else {
 			None
-		};
+		}
+	}
+
+	fn select_particle(&mut self, c: V2) {
+		self.particle_id = self.nearest_particle(c);
 	}
 
 	fn send(&mut self, msg: ControllerMessage) {
@@ -171,6 +231,7 @@ impl Viewer {
 						}
 					}
 					last_cursor = c;
+					self.cursor = c;
 				}
 				WindowEvent::MouseInput {
 					button: b,
@@ -246,9 +307,50 @@ impl Viewer {
 						.bytes()
 						.collect();
 					self.vkr.set_text("con", con_text, false);
+					let frame_text = match info.history {
+						Some([first, last]) => {
+							format!(
+								"frame: {} ({}-{})",
+								info.frame, first, last
+							)
+						}
+						None => format!("frame: {}", info.frame),
+					};
+					self.vkr.set_text(
+						"frame",
+						frame_text.bytes().collect(),
+						false,
+					);
+					self.frame = info.frame;
 					self.last_model = Some(pr_model);
 					self.update_flag = true;
 				}
+				UserEvent::ModelAdded {
+					constraints,
+					payload,
+					..
+				} => {
+					// empty for a model without texture
+					if payload.is_empty() {
+						return;
+					}
+					match ModelTextures::decode(&payload) {
+						Ok(mut textures) => {
+							let base = self.next_texture as i32;
+							textures.offset_texture_ids(base);
+							self.next_texture += textures.textures.len();
+							for texture in textures.textures.into_iter() {
+								self.vkr.add_texture(texture);
+							}
+							self.indexer
+								.borrow_mut()
+								.add_faces(&constraints, textures.faces);
+						}
+						Err(e) => {
+							eprintln!("ERROR: texture of added model: {}", e);
+						}
+					}
+				}
 			},
 			Event::MainEventsCleared => {
 				std::thread::sleep(std::time::Duration::from_millis(10));
@@ -287,6 +389,42 @@ impl Viewer {
 					self.vkr.set_render_mode(self.render_mode);
 				}
 			}
+			b'd' => {
+				let hovered = self.nearest_particle(self.cursor);
+				let msg = match self.input_buffer.get(1) {
+					Some(b'p') => {
+						hovered.map(ControllerMessage::RemoveParticle)
+					}
+					Some(b'm') => hovered
+						.and_then(|id| {
+							Some(self.last_model.as_ref()?.particles[&id].model)
+						})
+						.map(ControllerMessage::RemoveModel),
+					Some(b'o') => Some(ControllerMessage::RemoveOrphans),
+					Some(b'c') => Some(ControllerMessage::Clear),
+					Some(_) => None,
+					None => return,
+				};
+				if let Some(msg) = msg {
+					self.send(msg);
+				}
+			}
+			b'f' => {
+				let path = SNAPSHOT_PATH.to_string();
+				let msg = match self.input_buffer.get(1) {
+					Some(b's') => Some(ControllerMessage::SaveSnapshot(path)),
+					Some(b'l') => Some(ControllerMessage::LoadSnapshot(path)),
+					Some(_) => None,
+					None => return,
+				};
+				if let Some(msg) = msg {
+					self.send(msg);
+				}
+			}
+			b'b' => self.send(ControllerMessage::Scrub(-1)),
+			b'n' => self.send(ControllerMessage::Scrub(1)),
+			b'g' => self.send(ControllerMessage::ResumeFrom(self.frame)),
+			b'a' => self.spawn_model(),
 			b' ' => self.send(ControllerMessage::TogglePause),
 			b's' => self.send(ControllerMessage::FrameForward),
 			_ => {}
@@ -44,6 +44,15 @@ impl VkRender {
 		self.r_world.set_render_mode(render_mode);
 	}
 
+	pub fn texture_len(&self) -> usize {
+		self.r_world.texture_len()
+	}
+
+	// texture id of the new texture is the previous texture_len
+	pub fn add_texture(&mut self, texture: TextureData) {
+		self.r_world.add_texture(texture);
+	}
+
 	pub fn new<E>(
 		el: &EventLoopWindowTarget<E>,
 		window_size: [u32; 2],
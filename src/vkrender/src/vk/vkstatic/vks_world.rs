@@ -14,7 +14,7 @@ use crate::vk::vkwrapper::*;
 use material::face::TextureData;
 use material::render_model::RenderModel;
 use material::texture_indexer::TextureIndexerRef;
-use protocol::pr_model::PrModel;
+use protocol::pr_model::{PrConstraintType, PrModel};
 
 type VertexBuffer<V> = Arc<CpuAccessibleBuffer<[V]>>;
 type VertexBuffers<V> = Vec<(i32, VertexBuffer<V>)>;
@@ -27,10 +27,11 @@ pub struct VksWorld {
 	pipeline_wf: VkwPipeline,
 	render_pass: VkwRenderPass,
 	texture_set: VkwTextureSet,
+	// kept for rebuilding the texture array when a layer is added
+	textures: Vec<TextureData>,
 
 	render_mode: RenderMode,
 	indexer: TextureIndexerRef,
-	tex_coords: VkwTexCoords,
 
 	pub primitives: Vec<VertexWf>,
 }
@@ -49,8 +50,8 @@ impl VksWorld {
 
 		let framebuffers =
 			window_size_dependent_setup(render_pass.clone(), &vks.images);
-		let (texture_set, tex_coords) = get_textures(
-			textures,
+		let texture_set = get_textures(
+			textures.clone(),
 			vks.device.clone(),
 			vks.queue.clone(),
 			pipeline.clone(),
@@ -62,10 +63,10 @@ impl VksWorld {
 			pipeline_wf,
 			render_pass,
 			texture_set,
+			textures,
 
 			render_mode: RenderMode::default(),
 			indexer,
-			tex_coords,
 
 			primitives: Vec::new(),
 		}
@@ -75,13 +76,28 @@ impl VksWorld {
 		self.render_mode = render_mode;
 	}
 
+	pub fn texture_len(&self) -> usize {
+		self.textures.len()
+	}
+
+	// image is immutable, so the whole array is uploaded again
+	pub fn add_texture(&mut self, texture: TextureData) {
+		self.textures.push(texture);
+		self.texture_set = get_textures(
+			self.textures.clone(),
+			self.vks.device.clone(),
+			self.vks.queue.clone(),
+			self.pipeline.clone(),
+		);
+	}
+
 	fn generate_vertex_buffers(
 		&self,
 		render_model: &RenderModel,
 	) -> VertexBuffers<Vertex> {
 		let mut vertex_buffers = vec![];
 		for (&id, face_group) in &render_model.face_groups {
-			if id < 0 || id >= self.tex_coords.len() as i32 {
+			if id < 0 || id >= self.textures.len() as i32 {
 				continue;
 			}
 			let vertices = face_group
@@ -90,7 +106,7 @@ impl VksWorld {
 				.flat_map(|x| {
 					(0..3).map(|i| Vertex {
 						pos: *render_model.vs.get(&x.vid[i]).unwrap(),
-						tex_coord: self.tex_coords[id as usize][x.uvid[i]],
+						tex_coord: x.uv[i],
 					})
 				})
 				.collect::<Vec<_>>();
@@ -117,6 +133,21 @@ impl VksWorld {
 		if self.render_mode.world_box {
 			vertices.extend(self.primitives.clone());
 		}
+		for particle in pr_model.particles.values() {
+			if !particle.fluid {
+				continue;
+			}
+			let [x, y] = particle.pos;
+			let r = 0.02;
+			vertices.extend(
+				[[x - r, y], [x + r, y], [x, y - r], [x, y + r]]
+					.into_iter()
+					.map(|pos| VertexWf {
+						color: [0.3, 0.5, 1.0, 0.8],
+						pos,
+					}),
+			);
+		}
 		if self.render_mode.constraint {
 			for constraint in &pr_model.constraints {
 				let mut positions = vec![];
@@ -135,6 +166,16 @@ impl VksWorld {
 						pos: positions[i],
 					}));
 				}
+				// both arms of a joint
+				if constraint.ty == PrConstraintType::Angle
+					&& positions.len() == 3
+				{
+					let arms = [0, 1, 1, 2].map(|i| VertexWf {
+						color: [1.0, 0.6, 0.0, 0.6],
+						pos: positions[i],
+					});
+					vertices.extend(arms);
+				}
 			}
 		}
 		if vertices.is_empty() {
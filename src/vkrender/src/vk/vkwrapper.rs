@@ -44,7 +44,6 @@ pub type VkwQueue = Arc<Queue>;
 pub type VkwRenderPass = Arc<RenderPass>;
 pub type VkwSurface<W> = Arc<Surface<W>>;
 pub type VkwSwapchain<W> = Arc<Swapchain<W>>;
-pub type VkwTexCoords = Vec<Vec<[f32; 2]>>;
 pub type VkwTextureSet = Arc<PersistentDescriptorSet>;
 
 pub fn get_device_and_queue<W>(
@@ -278,20 +277,12 @@ pub fn get_textures(
 	device: VkwDevice,
 	queue: VkwQueue,
 	pipeline: VkwPipeline,
-) -> (Arc<PersistentDescriptorSet>, VkwTexCoords) {
+) -> Arc<PersistentDescriptorSet> {
 	let tex_len = textures.len() as u32;
-	let (arrays, tex_coords): (Vec<Vec<u8>>, Vec<Vec<[f32; 2]>>) = textures
+	let arrays: Vec<Vec<u8>> = textures
 		.into_iter()
-		.map(|t| {
-			(
-				t.image.as_raw().clone(),
-				t.tex_coords
-					.into_iter()
-					.map(|x| x.into())
-					.collect::<Vec<[f32; 2]>>(),
-			)
-		})
-		.unzip();
+		.map(|t| t.image.as_raw().clone())
+		.collect();
 	let (texture, tex_future) = {
 		let dimensions = ImageDimensions::Dim2d {
 			width: 1024,
@@ -332,7 +323,7 @@ pub fn get_textures(
 	)
 	.unwrap();
 	tex_future.flush().unwrap();
-	(texture_set, tex_coords)
+	texture_set
 }
 
 pub fn window_size_dependent_setup(
@@ -0,0 +1,99 @@
+// cargo run --release -p xpbd --example bench
+use xpbd::constraint::constraint_template::ConstraintTemplate;
+use xpbd::constraint::distance::{
+	DistanceConstraintTemplate, DistanceConstraintType,
+};
+use xpbd::constraint::volume::VolumeConstraintTemplate;
+use xpbd::fluid_model::FluidModelBuilder;
+use xpbd::particle::ParticleTemplate;
+use xpbd::physical_model::PhysicalModel;
+use xpbd::posbox::Posbox;
+use xpbd::pworld::PWorld;
+use xpbd::V2;
+
+// n x n soft square, triangulated
+fn soft_square(n: usize, spacing: f32) -> PhysicalModel {
+	let mut model = PhysicalModel::default();
+	let id = |x: usize, y: usize| x * n + y;
+	for x in 0..n {
+		for y in 0..n {
+			let pos = V2::new(x as f32, y as f32) * spacing;
+			let p = ParticleTemplate::new(1.0, pos, spacing / 2.0);
+			model.particles.push(p);
+		}
+	}
+	let mut edges = Vec::new();
+	for x in 0..n {
+		for y in 0..n {
+			if x > 0 {
+				edges.push([id(x, y), id(x - 1, y)]);
+			}
+			if y > 0 {
+				edges.push([id(x, y), id(x, y - 1)]);
+			}
+			if x > 0 && y > 0 {
+				edges.push([id(x, y), id(x - 1, y - 1)]);
+				model.constraints.push(ConstraintTemplate::Volume(
+					VolumeConstraintTemplate {
+						ps: vec![id(x, y), id(x - 1, y), id(x - 1, y - 1)],
+						compliance: 1e-7,
+					},
+				));
+				model.constraints.push(ConstraintTemplate::Volume(
+					VolumeConstraintTemplate {
+						ps: vec![id(x, y), id(x - 1, y - 1), id(x, y - 1)],
+						compliance: 1e-7,
+					},
+				));
+			}
+		}
+	}
+	for ps in edges.into_iter() {
+		let l0 = (model.particles[ps[0]].pos - model.particles[ps[1]].pos)
+			.magnitude();
+		model.constraints.push(ConstraintTemplate::Distance(
+			DistanceConstraintTemplate {
+				ps: ps.to_vec(),
+				l0,
+				compliance: 1e-6,
+				ty: DistanceConstraintType::Normal,
+				break_range: None,
+			},
+		));
+	}
+	model
+}
+
+fn main() {
+	let mut pworld = PWorld::default().with_posbox(Posbox {
+		xmin: -8.0,
+		xmax: 8.0,
+		ymin: -30.0,
+		ymax: 0.0,
+	});
+	for i in 0..8 {
+		let offset =
+			V2::new((i % 4) as f32 * 3.5 - 7.0, -4.0 - (i / 4) as f32 * 3.5);
+		pworld.add_model(soft_square(30, 0.1), offset);
+	}
+	let mut fluid = FluidModelBuilder::default();
+	fluid.add_rect(V2::new(-7.0, -20.0), [80, 80]);
+	pworld.add_model(fluid.build_physical_model(), V2::new(0.0, 0.0));
+
+	for _ in 0..10 {
+		pworld.run();
+	}
+	let frames = 50;
+	let start = std::time::Instant::now();
+	for _ in 0..frames {
+		pworld.run();
+	}
+	let elapsed = start.elapsed().as_secs_f32();
+	let model = pworld.pr_model();
+	println!(
+		"{} particles, {} constraints, {:.2} ms/frame",
+		model.particles.len(),
+		model.constraints.len(),
+		elapsed * 1e3 / frames as f32,
+	);
+}
@@ -0,0 +1,186 @@
+use crate::constraint::{replace_id, rp, Constraint};
+use crate::particle::Particles;
+use crate::V2;
+use protocol::pr_model::{PrConstraint, PrConstraintType};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct AngleConstraintTemplate {
+	// ps[1] is the hinge
+	pub ps: Vec<usize>,
+	pub a0: f32,
+	pub compliance: f32,
+	pub limit: Option<[f32; 2]>,
+}
+
+// ccw angle from p0 - p1 to p2 - p1, in (-pi, pi]
+fn angle_p(p0: V2, p1: V2, p2: V2) -> f32 {
+	let a = p0 - p1;
+	let b = p2 - p1;
+	(a[0] * b[1] - a[1] * b[0]).atan2(a.dot(&b))
+}
+
+fn wrap_angle(a: f32) -> f32 {
+	use std::f32::consts::PI;
+	let a = (a + PI).rem_euclid(2.0 * PI) - PI;
+	if a == -PI {
+		PI
+	} else {
+		a
+	}
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct AngleConstraint {
+	// ps[1] is the hinge
+	ps: [usize; 3],
+	a0: f32,
+	lambda: f32,
+	compliance: f32,
+	limit: Option<[f32; 2]>,
+}
+
+impl AngleConstraint {
+	pub fn new(particles: &Particles, ps: [usize; 3]) -> Self {
+		let pos = ps.map(|id| particles.pos(id));
+		let a0 = angle_p(pos[0], pos[1], pos[2]);
+		Self::new_with_a0(ps, a0)
+	}
+
+	pub fn new_with_a0(ps: [usize; 3], a0: f32) -> Self {
+		Self {
+			ps,
+			a0: wrap_angle(a0),
+			lambda: 0f32,
+			compliance: 1e-6,
+			limit: None,
+		}
+	}
+
+	// the constraint is only active when the angle is outside of range
+	pub fn with_limit(mut self, limit: [f32; 2]) -> Self {
+		self.limit = Some(limit);
+		self
+	}
+
+	pub fn with_compliance(mut self, c: f32) -> Self {
+		self.compliance = c;
+		self
+	}
+
+	pub fn build(self) -> Box<dyn Constraint> {
+		Box::new(self)
+	}
+}
+
+#[typetag::serde]
+impl Constraint for AngleConstraint {
+	fn render(&self, id: i32) -> PrConstraint {
+		PrConstraint {
+			id,
+			ty: PrConstraintType::Angle,
+			particles: self.ps.to_vec(),
+		}
+	}
+
+	fn replace_particle(&mut self, id: usize, new_id: usize) {
+		replace_id(&mut self.ps, id, new_id);
+	}
+
+	fn pre_iteration(&mut self, _particles: &Particles) -> bool {
+		self.lambda = 0f32;
+		true
+	}
+
+	fn step(&mut self, dt: f32, particles: &Particles) {
+		let imass = self.ps.map(|id| particles.imass(id));
+		if imass.iter().sum::<f32>() == 0.0 {
+			return;
+		}
+		let pos = self.ps.map(|id| particles.pos(id));
+		let a = pos[0] - pos[1];
+		let b = pos[2] - pos[1];
+		let la = a.magnitude_squared();
+		let lb = b.magnitude_squared();
+		if !la.is_normal() || !lb.is_normal() {
+			eprintln!("WARN: bad angle arm {} {}", la, lb);
+			for id in self.ps {
+				particles.add_pos(id, rp(particles, id));
+			}
+			return;
+		}
+		let angle = angle_p(pos[0], pos[1], pos[2]);
+		let c = match self.limit {
+			None => wrap_angle(angle - self.a0),
+			Some([min, max]) => {
+				// offset from the middle, so a range across pi is whole
+				let half = (max - min) / 2.0;
+				let d = wrap_angle(angle - (min + max) / 2.0);
+				if d.abs() <= half {
+					return;
+				}
+				d - d.clamp(-half, half)
+			}
+		};
+		let grad0 = V2::new(a[1], -a[0]) / la;
+		let grad2 = V2::new(-b[1], b[0]) / lb;
+		let grad1 = -grad0 - grad2;
+		let grads = [grad0, grad1, grad2];
+
+		let beta: f32 = (0..3)
+			.map(|i| imass[i] * grads[i].magnitude_squared())
+			.sum();
+		let compliance_t = self.compliance / dt.powi(2);
+		let dlambda = (-c - compliance_t * self.lambda) / (beta + compliance_t);
+		self.lambda += dlambda;
+		for i in 0..3 {
+			particles.add_pos(self.ps[i], dlambda * imass[i] * grads[i]);
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::particle::Particle;
+
+	#[test]
+	fn test_angle_p() {
+		let p0 = V2::new(1., 0.);
+		let p1 = V2::new(0., 0.);
+		let p2 = V2::new(0., 1.);
+		let a0 = angle_p(p0, p1, p2);
+		let a1 = angle_p(p2, p1, p0);
+		assert!((a0 - std::f32::consts::FRAC_PI_2).abs() < 1e-6);
+		assert!((a0 + a1).abs() < 1e-6);
+		assert!(
+			(wrap_angle(a0 + 4.0 * std::f32::consts::PI) - a0).abs() < 1e-5
+		);
+	}
+
+	// range [2.8, 3.5] contains pi, angles are in (-pi, pi]
+	#[test]
+	fn test_limit_across_pi() {
+		let solve = |a: f32| {
+			let mut particles = Particles::default();
+			let mut add = |imass, pos| {
+				particles.add(Particle::new(imass, pos, V2::zeros()))
+			};
+			let p0 = add(0.0, V2::new(1., 0.));
+			let p1 = add(0.0, V2::new(0., 0.));
+			let p2 = add(1.0, V2::new(a.cos(), a.sin()));
+			let mut c = AngleConstraint::new(&particles, [p0, p1, p2])
+				.with_limit([2.8, 3.5])
+				.with_compliance(0.0);
+			for _ in 0..10 {
+				c.step(0.01, &particles);
+			}
+			let pos = c.ps.map(|id| particles.pos(id));
+			angle_p(pos[0], pos[1], pos[2])
+		};
+		assert!((solve(-3.0) + 3.0).abs() < 1e-5);
+		assert!((solve(3.0) - 3.0).abs() < 1e-5);
+		assert!((solve(2.5) - 2.8).abs() < 1e-3);
+		assert!((solve(-2.6) - wrap_angle(3.5)).abs() < 1e-3);
+	}
+}
@@ -1,8 +1,72 @@
-use super::distance::DistanceConstraintTemplate;
-use super::volume::VolumeConstraintTemplate;
+use super::angle::{AngleConstraint, AngleConstraintTemplate};
+use super::distance::{DistanceConstraint, DistanceConstraintTemplate};
+use super::shape_match::{ShapeMatchConstraint, ShapeMatchConstraintTemplate};
+use super::volume::{VolumeConstraint, VolumeConstraintTemplate};
+use super::CRef;
+use crate::particle::Particles;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub enum ConstraintTemplate {
 	Distance(DistanceConstraintTemplate),
 	Volume(VolumeConstraintTemplate),
+	Angle(AngleConstraintTemplate),
+	ShapeMatch(ShapeMatchConstraintTemplate),
+}
+
+impl ConstraintTemplate {
+	// model particle idx
+	pub fn particles(&self) -> &[usize] {
+		use ConstraintTemplate::*;
+		match self {
+			Distance(ct) => &ct.ps,
+			Volume(ct) => &ct.ps,
+			Angle(ct) => &ct.ps,
+			ShapeMatch(ct) => &ct.ps,
+		}
+	}
+
+	// joins nearby particles only, shape matching spans the whole model
+	pub fn is_local(&self) -> bool {
+		!matches!(self, ConstraintTemplate::ShapeMatch(_))
+	}
+
+	// id_map: model particle idx to particle id
+	pub fn instantiate(self, id_map: &[usize], particles: &Particles) -> CRef {
+		use ConstraintTemplate::*;
+		match self {
+			Distance(ct) => {
+				let p1 = id_map[ct.ps[0]];
+				let p2 = id_map[ct.ps[1]];
+				let mut con = DistanceConstraint::new_with_l0(p1, p2, ct.l0)
+					.with_compliance(ct.compliance)
+					.with_ty(ct.ty);
+				if let Some(range) = ct.break_range {
+					con = con.with_break_range(range);
+				}
+				con.build()
+			}
+			Volume(ct) => {
+				let ps = [0, 1, 2].map(|i| id_map[ct.ps[i]]);
+				VolumeConstraint::new(particles, ps)
+					.with_compliance(ct.compliance)
+					.build()
+			}
+			Angle(ct) => {
+				let ps = [0, 1, 2].map(|i| id_map[ct.ps[i]]);
+				let mut con = AngleConstraint::new_with_a0(ps, ct.a0)
+					.with_compliance(ct.compliance);
+				if let Some(limit) = ct.limit {
+					con = con.with_limit(limit);
+				}
+				con.build()
+			}
+			ShapeMatch(ct) => {
+				let ps = ct.ps.iter().map(|&i| id_map[i]).collect();
+				ShapeMatchConstraint::new(particles, ps)
+					.with_compliance(ct.compliance)
+					.build()
+			}
+		}
+	}
 }
@@ -0,0 +1,185 @@
+use crate::constraint::{rp, Constraint};
+use crate::particle::{Particles, Surface};
+use crate::V2;
+use protocol::pr_model::{PrConstraint, PrConstraintType};
+use serde::{Deserialize, Serialize};
+
+// tangent correction of relative displacement dx in a contact
+// d: penetration depth
+pub fn friction_correction(dx: V2, n: V2, d: f32, surface: &Surface) -> V2 {
+	let dt = dx - n * dx.dot(&n);
+	let l = dt.magnitude();
+	if l <= surface.static_friction * d {
+		dt
+	} else {
+		dt * (surface.kinetic_friction * d / l).min(1.0)
+	}
+}
+
+// restitution is skipped for slow contacts(e.g. resting under gravity)
+pub fn restitution_threshold(accel: f32, dt: f32) -> f32 {
+	2.0 * accel * dt * dt
+}
+
+// repulsive only particle-particle contact with friction and restitution
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ContactConstraint {
+	ps: [usize; 2],
+	l0: f32,
+	lambda: f32,
+	compliance: f32,
+	surface: Surface,
+	// relative normal displacement before solving, for restitution
+	vn0: f32,
+	active: bool,
+	dt: f32,
+}
+
+impl ContactConstraint {
+	pub fn new(particles: &Particles, p1: usize, p2: usize, l0: f32) -> Self {
+		let dp = particles.pos(p1) - particles.pos(p2);
+		let l = dp.magnitude();
+		let vn0 = if l == 0.0 {
+			0.0
+		} else {
+			(particles.dpos(p1) - particles.dpos(p2)).dot(&dp) / l
+		};
+		Self {
+			ps: [p1, p2],
+			l0,
+			lambda: 0f32,
+			compliance: 1e-7,
+			surface: particles.surface(p1).mix(particles.surface(p2)),
+			vn0,
+			active: false,
+			dt: 0f32,
+		}
+	}
+
+	pub fn with_compliance(mut self, c: f32) -> Self {
+		self.compliance = c;
+		self
+	}
+
+	pub fn build(self) -> Box<dyn Constraint> {
+		Box::new(self)
+	}
+}
+
+#[typetag::serde]
+impl Constraint for ContactConstraint {
+	fn render(&self, id: i32) -> PrConstraint {
+		PrConstraint {
+			id,
+			ty: PrConstraintType::Other,
+			particles: self.ps.to_vec(),
+		}
+	}
+
+	fn pre_iteration(&mut self, _particles: &Particles) -> bool {
+		self.lambda = 0f32;
+		true
+	}
+
+	fn step(&mut self, dt: f32, particles: &Particles) {
+		self.dt = dt;
+		let [id1, id2] = self.ps;
+		let imass1 = particles.imass(id1);
+		let imass2 = particles.imass(id2);
+		let imass = imass1 + imass2;
+		if imass == 0.0 {
+			return;
+		}
+		let dp = particles.pos(id1) - particles.pos(id2);
+		let l = dp.magnitude();
+		if !l.is_normal() {
+			eprintln!("WARN: bad contact distance {}", l);
+			particles.add_pos(id1, rp(particles, id1));
+			particles.add_pos(id2, rp(particles, id2));
+			return;
+		}
+		let dl = l - self.l0;
+		if dl >= 0. {
+			return;
+		}
+		self.active = true;
+		let n = dp / l;
+		let compliance_t = self.compliance / dt.powi(2);
+		let dlambda =
+			(-dl - compliance_t * self.lambda) / (imass + compliance_t);
+		self.lambda += dlambda;
+		particles.add_pos(id1, n * dlambda * imass1);
+		particles.add_pos(id2, -n * dlambda * imass2);
+
+		let dx = particles.dpos(id1) - particles.dpos(id2);
+		let correct = friction_correction(dx, n, -dl, &self.surface) / imass;
+		particles.add_pos(id1, -correct * imass1);
+		particles.add_pos(id2, correct * imass2);
+	}
+
+	fn update_velocity(&mut self, particles: &mut Particles) {
+		if !self.active || self.surface.restitution == 0.0 {
+			return;
+		}
+		let [id1, id2] = self.ps;
+		let imass1 = particles.imass(id1);
+		let imass2 = particles.imass(id2);
+		let imass = imass1 + imass2;
+		let accel = particles
+			.accel(id1)
+			.magnitude()
+			.max(particles.accel(id2).magnitude());
+		if imass == 0.0 || -self.vn0 <= restitution_threshold(accel, self.dt) {
+			return;
+		}
+		let n = (particles.pos(id1) - particles.pos(id2)).normalize();
+		let vn = (particles.dpos(id1) - particles.dpos(id2)).dot(&n);
+		let dv = -self.surface.restitution * self.vn0 - vn;
+		if dv <= 0.0 {
+			return;
+		}
+		// velocity is pos - ppos
+		let ppos1 = particles.ppos(id1) - n * dv * imass1 / imass;
+		let ppos2 = particles.ppos(id2) + n * dv * imass2 / imass;
+		particles.set_ppos(id1, ppos1);
+		particles.set_ppos(id2, ppos2);
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::particle::Particle;
+
+	// drop from 1 onto a fixed particle, bounces to restitution^2
+	#[test]
+	fn test_bounce() {
+		let dt = 1.0 / 600.0;
+		let mut particles = Particles::default();
+		let mut add = |imass, y| {
+			let mut p = Particle::new(imass, V2::new(0., y), V2::new(0., 9.8));
+			p.surface.restitution = 0.5;
+			particles.add(p)
+		};
+		let floor = add(0.0, 0.0);
+		let ball = add(1.0, -1.08);
+		let mut top = f32::INFINITY;
+		let mut bounced = false;
+		for _ in 0..1200 {
+			particles.update(ball, dt, f32::INFINITY);
+			let l = (particles.pos(ball) - particles.pos(floor)).magnitude();
+			if l < 0.08 {
+				let mut c =
+					ContactConstraint::new(&particles, ball, floor, 0.08);
+				c.pre_iteration(&particles);
+				c.step(dt, &particles);
+				c.update_velocity(&mut particles);
+				bounced = true;
+			} else if bounced {
+				top = top.min(particles.pos(ball)[1]);
+			}
+		}
+		let h = -top - 0.08;
+		assert!((h - 0.25).abs() < 0.02, "{}", h);
+	}
+}
@@ -0,0 +1,130 @@
+use std::f32::consts::PI;
+
+use crate::constraint::Constraint;
+use crate::particle::Particles;
+use crate::V2;
+use protocol::pr_model::{PrConstraint, PrConstraintType};
+use serde::{Deserialize, Serialize};
+
+// 2d poly6 kernel
+pub fn poly6(r2: f32, h: f32) -> f32 {
+	let h2 = h * h;
+	if r2 >= h2 {
+		return 0.0;
+	}
+	4.0 / (PI * h2.powi(4)) * (h2 - r2).powi(3)
+}
+
+// 2d spiky kernel gradient
+pub fn spiky_grad(r: V2, h: f32) -> V2 {
+	let l = r.magnitude();
+	if l >= h || l == 0.0 {
+		return V2::new(0.0, 0.0);
+	}
+	-30.0 / (PI * h.powi(5)) * (h - l).powi(2) * r / l
+}
+
+// density of an infinite square lattice of unit mass particles
+pub fn lattice_density(h: f32, spacing: f32) -> f32 {
+	let n = (h / spacing).ceil() as i32;
+	let mut result = 0.0;
+	for x in -n..=n {
+		for y in -n..=n {
+			let r = V2::new(x as f32, y as f32) * spacing;
+			result += poly6(r.magnitude_squared(), h);
+		}
+	}
+	result
+}
+
+// position based fluids, one constraint per fluid particle
+// one-sided: density above rho0 is pushed back, lower density is left
+// alone(c is clamped at 0), so fluid does not pull itself together,
+// there is no surface tension and free fluid spreads out
+#[derive(Clone, Serialize, Deserialize)]
+pub struct DensityConstraint {
+	// ps[0] is the center particle
+	ps: Vec<usize>,
+	h: f32,
+	rho0: f32,
+	lambda: f32,
+	compliance: f32,
+}
+
+impl DensityConstraint {
+	pub fn new(p: usize, neighbors: Vec<usize>, h: f32, rho0: f32) -> Self {
+		let mut ps = vec![p];
+		ps.extend(neighbors);
+		Self {
+			ps,
+			h,
+			rho0,
+			lambda: 0f32,
+			compliance: 1e-6,
+		}
+	}
+
+	pub fn with_compliance(mut self, c: f32) -> Self {
+		self.compliance = c;
+		self
+	}
+
+	pub fn build(self) -> Box<dyn Constraint> {
+		Box::new(self)
+	}
+}
+
+#[typetag::serde]
+impl Constraint for DensityConstraint {
+	fn render(&self, id: i32) -> PrConstraint {
+		PrConstraint {
+			id,
+			ty: PrConstraintType::Other,
+			particles: self.ps.clone(),
+		}
+	}
+
+	fn pre_iteration(&mut self, _particles: &Particles) -> bool {
+		self.lambda = 0f32;
+		true
+	}
+
+	fn step(&mut self, dt: f32, particles: &Particles) {
+		let len = self.ps.len();
+		let imass: Vec<f32> =
+			self.ps.iter().map(|&id| particles.imass(id)).collect();
+		if imass[0] == 0.0 {
+			return;
+		}
+		let pos: Vec<V2> =
+			self.ps.iter().map(|&id| particles.pos(id)).collect();
+		let mut rho = 0.0;
+		let mut grads = vec![V2::new(0.0, 0.0); len];
+		for k in 0..len {
+			let r = pos[0] - pos[k];
+			let mass = if imass[k] == 0.0 { 0.0 } else { 1.0 / imass[k] };
+			rho += mass * poly6(r.magnitude_squared(), self.h);
+			if k > 0 {
+				let grad = mass * spiky_grad(r, self.h) / self.rho0;
+				grads[0] += grad;
+				grads[k] = -grad;
+			}
+		}
+		let c = (rho / self.rho0 - 1.0).max(0.0);
+		if c == 0.0 {
+			return;
+		}
+		let beta: f32 = (0..len)
+			.map(|k| imass[k] * grads[k].magnitude_squared())
+			.sum();
+		let compliance_t = self.compliance / dt.powi(2);
+		if beta + compliance_t == 0.0 {
+			return;
+		}
+		let dlambda = (-c - compliance_t * self.lambda) / (beta + compliance_t);
+		self.lambda += dlambda;
+		for k in 0..len {
+			particles.add_pos(self.ps[k], dlambda * imass[k] * grads[k]);
+		}
+	}
+}
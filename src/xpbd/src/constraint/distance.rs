@@ -1,17 +1,19 @@
-use crate::constraint::particle_list::ParticleList;
-use crate::constraint::{rp, Constraint};
-use crate::particle::PRef;
-use protocol::pr_model::PrConstraint;
+use crate::constraint::{replace_id, rp, Constraint};
+use crate::particle::Particles;
+use protocol::pr_model::{PrConstraint, PrConstraintType};
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct DistanceConstraintTemplate {
 	pub ps: Vec<usize>,
 	pub l0: f32,
 	pub compliance: f32,
 	pub ty: DCTy,
+	// relative to l0
+	pub break_range: Option<[f32; 2]>,
 }
 
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum DistanceConstraintType {
 	Normal,
 	Repulsive, // collision
@@ -19,10 +21,9 @@ pub enum DistanceConstraintType {
 }
 type DCTy = DistanceConstraintType;
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct DistanceConstraint {
-	ps: ParticleList,
-	ps_sort: ParticleList,
+	ps: [usize; 2],
 	l0: f32,
 	lambda: f32,
 	compliance: f32,
@@ -36,20 +37,14 @@ pub struct DistanceConstraint {
 }
 
 impl DistanceConstraint {
-	pub fn new(p1: PRef, p2: PRef) -> Self {
-		let pos1 = p1.try_read().unwrap().get_pos();
-		let pos2 = p2.try_read().unwrap().get_pos();
-		let l0 = (pos1 - pos2).magnitude();
+	pub fn new(particles: &Particles, p1: usize, p2: usize) -> Self {
+		let l0 = (particles.pos(p1) - particles.pos(p2)).magnitude();
 		Self::new_with_l0(p1, p2, l0)
 	}
 
-	pub fn new_with_l0(p1: PRef, p2: PRef, l0: f32) -> Self {
-		let ps = vec![p1, p2];
-		let ps_sort = ParticleList::new(ps.clone(), true);
-		let ps = ParticleList::new(ps, false);
+	pub fn new_with_l0(p1: usize, p2: usize, l0: f32) -> Self {
 		Self {
-			ps,
-			ps_sort,
+			ps: [p1, p2],
 			l0,
 			lambda: 0f32,
 			compliance: 1e-7,
@@ -78,6 +73,7 @@ impl DistanceConstraint {
 	}
 
 	pub fn with_break_range(mut self, range: [f32; 2]) -> Self {
+		self.break_self = true;
 		self.break_range[0] = range[0] * self.l0;
 		self.break_range[1] = range[1] * self.l0;
 		self
@@ -98,20 +94,38 @@ impl DistanceConstraint {
 	}
 }
 
+#[typetag::serde]
 impl Constraint for DistanceConstraint {
 	fn render(&self, id: i32) -> PrConstraint {
 		PrConstraint {
 			id,
-			particles: self.ps.ids(),
+			ty: PrConstraintType::Other,
+			particles: self.ps.to_vec(),
 		}
 	}
 
-	fn pre_iteration(&mut self) -> bool {
-		self.lambda = 0f32;
-		let p1 = self.ps[0].try_write().unwrap();
-		let p2 = self.ps[1].try_write().unwrap();
+	fn replace_particle(&mut self, id: usize, new_id: usize) {
+		replace_id(&mut self.ps, id, new_id);
+	}
 
-		let dp = p1.get_pos() - p2.get_pos();
+	fn rest(&self) -> Option<f32> {
+		Some(self.l0)
+	}
+
+	fn scale(&mut self, k: f32) {
+		self.scale_rest(k);
+	}
+
+	fn scale_rest(&mut self, k: f32) {
+		self.l0 *= k;
+		self.plas_cutoff *= k;
+		self.break_range[0] *= k;
+		self.break_range[1] *= k;
+	}
+
+	fn pre_iteration(&mut self, particles: &Particles) -> bool {
+		self.lambda = 0f32;
+		let dp = particles.pos(self.ps[0]) - particles.pos(self.ps[1]);
 		let l = dp.magnitude();
 		if self.break_range[0] > l || self.break_range[1] < l {
 			return !self.break_self;
@@ -126,21 +140,20 @@ impl Constraint for DistanceConstraint {
 		true
 	}
 
-	fn step(&mut self, dt: f32) {
-		let mut p1_mut = self.ps_sort[0].write().unwrap();
-		let mut p2_mut = self.ps_sort[1].write().unwrap();
-		let imass1 = p1_mut.get_imass();
-		let imass2 = p2_mut.get_imass();
+	fn step(&mut self, dt: f32, particles: &Particles) {
+		let [id1, id2] = self.ps;
+		let imass1 = particles.imass(id1);
+		let imass2 = particles.imass(id2);
 		let imass = imass1 + imass2;
 		if imass == 0.0 {
 			return;
 		}
-		let dp = p1_mut.get_pos() - p2_mut.get_pos();
+		let dp = particles.pos(id1) - particles.pos(id2);
 		let l = dp.magnitude();
 		if !l.is_normal() {
 			eprintln!("WARN: bad distance {}", l);
-			p1_mut.add_pos(rp());
-			p2_mut.add_pos(rp());
+			particles.add_pos(id1, rp(particles, id1));
+			particles.add_pos(id2, rp(particles, id2));
 			return;
 		}
 		let dl = l - self.l0;
@@ -156,7 +169,7 @@ impl Constraint for DistanceConstraint {
 		let correct = dlambda * dp / l;
 		self.lambda += dlambda;
 
-		p1_mut.add_pos(correct * imass1);
-		p2_mut.add_pos(-correct * imass2);
+		particles.add_pos(id1, correct * imass1);
+		particles.add_pos(id2, -correct * imass2);
 	}
 }
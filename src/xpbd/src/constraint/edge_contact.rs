@@ -0,0 +1,204 @@
+use crate::constraint::contact::{friction_correction, restitution_threshold};
+use crate::constraint::{rp, Constraint};
+use crate::particle::{Particles, Surface};
+use crate::V2;
+use protocol::pr_model::{PrConstraint, PrConstraintType};
+use serde::{Deserialize, Serialize};
+
+// closest point on segment ab to p, as lerp factor
+pub fn segment_t(p: V2, a: V2, b: V2) -> f32 {
+	let ab = b - a;
+	let l2 = ab.magnitude_squared();
+	if l2 == 0.0 {
+		return 0.0;
+	}
+	((p - a).dot(&ab) / l2).clamp(0.0, 1.0)
+}
+
+// side of line ab that p is on, 1 or -1
+pub fn edge_side(a: V2, b: V2, p: V2) -> f32 {
+	let (e, w) = (b - a, p - a);
+	if e[0] * w[1] - e[1] * w[0] >= 0.0 {
+		1.0
+	} else {
+		-1.0
+	}
+}
+
+// repulsive only, keeps particle out of the edge by thickness
+// a particle on the inside is pushed out along the outward normal
+#[derive(Clone, Serialize, Deserialize)]
+pub struct EdgeContactConstraint {
+	// particle, edge start, edge end
+	ps: [usize; 3],
+	// edge_side of the inside, 0 if unknown
+	side: f32,
+	thickness: f32,
+	lambda: f32,
+	compliance: f32,
+	surface: Surface,
+	// relative normal displacement before solving, for restitution
+	vn0: f32,
+	active: bool,
+	dt: f32,
+}
+
+// lerp factor of the closest point, unit normal and gap to thickness
+// outside, the normal is from the closest point, so edge ends are round
+// inside(tunnelled) and over the edge, it is the outward normal of the
+// edge, beyond the ends is outside of a convex corner
+fn contact(pos: [V2; 3], side: f32, thickness: f32) -> Option<(f32, V2, f32)> {
+	let t = segment_t(pos[0], pos[1], pos[2]);
+	let d = pos[0] - (pos[1] + (pos[2] - pos[1]) * t);
+	let e = pos[2] - pos[1];
+	// (e1, -e0) points to side -1
+	let outward = V2::new(e[1], -e[0]) * side;
+	let el = outward.magnitude();
+	if side != 0.0 && t > 0.0 && t < 1.0 && el.is_normal() {
+		let outward = outward / el;
+		let dn = d.dot(&outward);
+		if dn <= 0.0 {
+			return Some((t, outward, dn - thickness));
+		}
+	}
+	let l = d.magnitude();
+	if !l.is_normal() {
+		return None;
+	}
+	Some((t, d / l, l - thickness))
+}
+
+// relative displacement of particle to the closest point on edge
+fn relative_dpos(particles: &Particles, ps: [usize; 3], t: f32) -> V2 {
+	particles.dpos(ps[0])
+		- particles.dpos(ps[1]) * (1.0 - t)
+		- particles.dpos(ps[2]) * t
+}
+
+impl EdgeContactConstraint {
+	pub fn new(
+		particles: &Particles,
+		p: usize,
+		edge: [usize; 2],
+		side: f32,
+		thickness: f32,
+	) -> Self {
+		let ps = [p, edge[0], edge[1]];
+		let pos = ps.map(|id| particles.pos(id));
+		let vn0 = match contact(pos, side, thickness) {
+			Some((t, n, _)) => relative_dpos(particles, ps, t).dot(&n),
+			None => 0.0,
+		};
+		let surface = particles
+			.surface(ps[0])
+			.mix(&particles.surface(ps[1]).mix(particles.surface(ps[2])));
+		Self {
+			ps,
+			side,
+			thickness,
+			lambda: 0f32,
+			compliance: 1e-7,
+			surface,
+			vn0,
+			active: false,
+			dt: 0f32,
+		}
+	}
+
+	pub fn with_compliance(mut self, c: f32) -> Self {
+		self.compliance = c;
+		self
+	}
+
+	pub fn build(self) -> Box<dyn Constraint> {
+		Box::new(self)
+	}
+}
+
+#[typetag::serde]
+impl Constraint for EdgeContactConstraint {
+	fn render(&self, id: i32) -> PrConstraint {
+		PrConstraint {
+			id,
+			ty: PrConstraintType::Other,
+			particles: self.ps.to_vec(),
+		}
+	}
+
+	fn pre_iteration(&mut self, _particles: &Particles) -> bool {
+		self.lambda = 0f32;
+		true
+	}
+
+	fn step(&mut self, dt: f32, particles: &Particles) {
+		self.dt = dt;
+		let ps = self.ps;
+		let imass = ps.map(|id| particles.imass(id));
+		let pos = ps.map(|id| particles.pos(id));
+		let (t, n, dl) = match contact(pos, self.side, self.thickness) {
+			Some(x) => x,
+			None => {
+				eprintln!("WARN: bad edge contact at {}", ps[0]);
+				particles.add_pos(ps[0], rp(particles, ps[0]));
+				return;
+			}
+		};
+		if dl >= 0.0 {
+			return;
+		}
+		self.active = true;
+		let ks = [1.0, -(1.0 - t), -t];
+		let beta: f32 = (0..3).map(|i| imass[i] * ks[i] * ks[i]).sum();
+		if beta == 0.0 {
+			return;
+		}
+		let compliance_t = self.compliance / dt.powi(2);
+		let dlambda =
+			(-dl - compliance_t * self.lambda) / (beta + compliance_t);
+		self.lambda += dlambda;
+		for i in 0..3 {
+			particles.add_pos(ps[i], dlambda * imass[i] * ks[i] * n);
+		}
+
+		let dx = relative_dpos(particles, ps, t);
+		let correct = friction_correction(dx, n, -dl, &self.surface) / beta;
+		for i in 0..3 {
+			particles.add_pos(ps[i], -correct * imass[i] * ks[i]);
+		}
+	}
+
+	fn update_velocity(&mut self, particles: &mut Particles) {
+		if !self.active || self.surface.restitution == 0.0 {
+			return;
+		}
+		let ps = self.ps;
+		let accel = ps
+			.iter()
+			.map(|&id| particles.accel(id).magnitude())
+			.fold(0.0, f32::max);
+		if -self.vn0 <= restitution_threshold(accel, self.dt) {
+			return;
+		}
+		let imass = ps.map(|id| particles.imass(id));
+		let pos = ps.map(|id| particles.pos(id));
+		let (t, n, _) = match contact(pos, self.side, self.thickness) {
+			Some(x) => x,
+			None => return,
+		};
+		let ks = [1.0, -(1.0 - t), -t];
+		let beta: f32 = (0..3).map(|i| imass[i] * ks[i] * ks[i]).sum();
+		if beta == 0.0 {
+			return;
+		}
+		let dx = relative_dpos(particles, ps, t);
+		let dv = -self.surface.restitution * self.vn0 - dx.dot(&n);
+		if dv <= 0.0 {
+			return;
+		}
+		// velocity is pos - ppos
+		for i in 0..3 {
+			let ppos = particles.ppos(ps[i]) - n * dv * imass[i] * ks[i] / beta;
+			particles.set_ppos(ps[i], ppos);
+		}
+	}
+}
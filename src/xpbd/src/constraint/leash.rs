@@ -1,23 +1,23 @@
 use crate::constraint::Constraint;
-use crate::particle::PRef;
+use crate::particle::Particles;
 use crate::V2;
-use protocol::pr_model::PrConstraint;
+use protocol::pr_model::{PrConstraint, PrConstraintType};
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct LeashConstraint {
-	p: PRef,
+	p: usize,
 	pos: V2,
 	lambda: f32,
 	compliance: f32,
 }
 
 impl LeashConstraint {
-	pub fn new(p: PRef) -> Self {
-		let pos = p.try_read().unwrap().get_pos();
-		Self::new_with_pos(p, pos)
+	pub fn new(particles: &Particles, p: usize) -> Self {
+		Self::new_with_pos(p, particles.pos(p))
 	}
 
-	pub fn new_with_pos(p: PRef, pos: V2) -> Self {
+	pub fn new_with_pos(p: usize, pos: V2) -> Self {
 		Self {
 			p,
 			pos,
@@ -36,32 +36,33 @@ impl LeashConstraint {
 	}
 }
 
+#[typetag::serde]
 impl Constraint for LeashConstraint {
 	fn render(&self, id: i32) -> PrConstraint {
 		PrConstraint {
 			id,
-			particles: vec![self.p.try_read().unwrap().id],
+			ty: PrConstraintType::Other,
+			particles: vec![self.p],
 		}
 	}
 
-	fn pre_iteration(&mut self) -> bool {
+	fn pre_iteration(&mut self, _particles: &Particles) -> bool {
 		self.lambda = 0f32;
 		true
 	}
 
-	fn step(&mut self, dt: f32) {
-		let mut p = self.p.write().unwrap();
-		let imass = p.get_imass();
+	fn step(&mut self, dt: f32, particles: &Particles) {
+		let imass = particles.imass(self.p);
 		if imass == 0.0 {
 			return;
 		}
-		let dp = p.get_pos() - self.pos;
+		let dp = particles.pos(self.p) - self.pos;
 		let dl = dp.magnitude();
 		let compliance_t = self.compliance / dt.powi(2);
 		let dlambda =
 			(-dl - compliance_t * self.lambda) / (imass + compliance_t);
 		let correct = dlambda * dp / dl;
 		self.lambda += dlambda;
-		p.add_pos(correct * imass);
+		particles.add_pos(self.p, correct * imass);
 	}
 }
@@ -1,27 +1,64 @@
+pub mod angle;
 pub mod constraint_template;
+pub mod contact;
+pub mod density;
 pub mod distance;
+pub mod edge_contact;
 pub mod leash;
+pub mod obstacle_contact;
+pub mod shape_match;
 pub mod volume;
 
-mod particle_list;
-
-use crate::particle::PRef;
+use crate::particle::Particles;
 use crate::V2;
 use protocol::pr_model::PrConstraint;
 
 pub type CRef = Box<dyn Constraint>;
 
+// particles are referred by id, and all particles must be in render
+// constraints in one batch share no particle and are solved in parallel
+// serialized with their type name for snapshots
+#[typetag::serde]
 pub trait Constraint: dyn_clone::DynClone + Send {
-	fn pre_iteration(&mut self) -> bool;
-	fn step(&mut self, dt: f32);
+	fn pre_iteration(&mut self, particles: &Particles) -> bool;
+	fn step(&mut self, dt: f32, particles: &Particles);
 	fn render(&self, id: i32) -> PrConstraint;
+	// used by fracture, replace particle of id with new_id
+	fn replace_particle(&mut self, _id: usize, _new_id: usize) {}
+	// joins nearby particles only, fracture drops the others on a crack
+	fn is_local(&self) -> bool {
+		true
+	}
+	// used by remeshing, rest length or area
+	fn rest(&self) -> Option<f32> {
+		None
+	}
+	fn scale_rest(&mut self, _k: f32) {}
+	// model is scaled uniformly by k
+	fn scale(&mut self, _k: f32) {}
+	// after all iterations, e.g. restitution of contacts
+	fn update_velocity(&mut self, _particles: &mut Particles) {}
 }
 
 dyn_clone::clone_trait_object!(Constraint);
 
-pub fn rp() -> V2 {
+pub fn replace_id(ps: &mut [usize], id: usize, new_id: usize) {
+	for p in ps.iter_mut() {
+		if *p == id {
+			*p = new_id;
+		}
+	}
+}
+
+// small offset to separate coinciding particles
+// derived from the seed, particle id and position, so runs are reproducible
+pub fn rp(particles: &Particles, id: usize) -> V2 {
 	use rand::prelude::*;
-	let dx = rand::thread_rng().gen::<f32>() / 1e5;
-	let dy = rand::thread_rng().gen::<f32>() / 1e5;
+	let pos = particles.pos(id);
+	let bits = (pos[0].to_bits() as u64) << 32 | pos[1].to_bits() as u64;
+	let seed = particles.seed() ^ bits ^ (id as u64).rotate_left(17);
+	let mut rng = StdRng::seed_from_u64(seed);
+	let dx = rng.gen::<f32>() / 1e5;
+	let dy = rng.gen::<f32>() / 1e5;
 	V2::new(dx, dy)
 }
@@ -0,0 +1,112 @@
+use crate::constraint::contact::{friction_correction, restitution_threshold};
+use crate::constraint::Constraint;
+use crate::particle::{Particles, Surface};
+use crate::V2;
+use protocol::pr_model::{PrConstraint, PrConstraintType};
+use serde::{Deserialize, Serialize};
+
+// particle against static obstacle, surface linearized as a plane
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ObstacleContactConstraint {
+	p: usize,
+	// closest point on the obstacle surface
+	q: V2,
+	// outward normal
+	n: V2,
+	lambda: f32,
+	compliance: f32,
+	surface: Surface,
+	vn0: f32,
+	active: bool,
+	dt: f32,
+}
+
+impl ObstacleContactConstraint {
+	pub fn new(
+		particles: &Particles,
+		p: usize,
+		q: V2,
+		n: V2,
+		surface: &Surface,
+	) -> Self {
+		Self {
+			p,
+			q,
+			n,
+			lambda: 0f32,
+			compliance: 1e-7,
+			surface: particles.surface(p).mix(surface),
+			vn0: particles.dpos(p).dot(&n),
+			active: false,
+			dt: 0f32,
+		}
+	}
+
+	pub fn with_compliance(mut self, c: f32) -> Self {
+		self.compliance = c;
+		self
+	}
+
+	pub fn build(self) -> Box<dyn Constraint> {
+		Box::new(self)
+	}
+}
+
+#[typetag::serde]
+impl Constraint for ObstacleContactConstraint {
+	fn render(&self, id: i32) -> PrConstraint {
+		PrConstraint {
+			id,
+			ty: PrConstraintType::Other,
+			particles: vec![self.p],
+		}
+	}
+
+	fn pre_iteration(&mut self, _particles: &Particles) -> bool {
+		self.lambda = 0f32;
+		true
+	}
+
+	fn step(&mut self, dt: f32, particles: &Particles) {
+		self.dt = dt;
+		let imass = particles.imass(self.p);
+		if imass == 0.0 {
+			return;
+		}
+		let d = (particles.pos(self.p) - self.q).dot(&self.n)
+			- particles.radius(self.p);
+		if d >= 0. {
+			return;
+		}
+		self.active = true;
+		let compliance_t = self.compliance / dt.powi(2);
+		let dlambda =
+			(-d - compliance_t * self.lambda) / (imass + compliance_t);
+		self.lambda += dlambda;
+		particles.add_pos(self.p, self.n * dlambda * imass);
+
+		// obstacle does not move, all correction goes to the particle
+		let dx = particles.dpos(self.p);
+		let correct = friction_correction(dx, self.n, -d, &self.surface);
+		particles.add_pos(self.p, -correct);
+	}
+
+	fn update_velocity(&mut self, particles: &mut Particles) {
+		if !self.active || self.surface.restitution == 0.0 {
+			return;
+		}
+		let accel = particles.accel(self.p).magnitude();
+		if particles.imass(self.p) == 0.0
+			|| -self.vn0 <= restitution_threshold(accel, self.dt)
+		{
+			return;
+		}
+		let vn = particles.dpos(self.p).dot(&self.n);
+		let dv = -self.surface.restitution * self.vn0 - vn;
+		if dv <= 0.0 {
+			return;
+		}
+		let ppos = particles.ppos(self.p) - self.n * dv;
+		particles.set_ppos(self.p, ppos);
+	}
+}
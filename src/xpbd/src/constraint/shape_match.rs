@@ -0,0 +1,153 @@
+use crate::constraint::{replace_id, Constraint};
+use crate::particle::Particles;
+use crate::V2;
+use protocol::pr_model::{PrConstraint, PrConstraintType};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ShapeMatchConstraintTemplate {
+	pub ps: Vec<usize>,
+	// 0: rigid
+	pub compliance: f32,
+}
+
+// fixed particles are treated as very heavy
+fn weight(imass: f32) -> f32 {
+	1.0 / (imass + 1e-6)
+}
+
+// weighted center
+fn center(pos: &[V2], w: &[f32]) -> V2 {
+	let mut c = V2::new(0.0, 0.0);
+	for (p, w) in pos.iter().zip(w.iter()) {
+		c += p * *w;
+	}
+	c / w.iter().sum::<f32>()
+}
+
+// rotation angle best matching q to p, both relative to their centers
+fn fit_rotation(p: &[V2], q: &[V2], w: &[f32]) -> f32 {
+	// a = sum(w * p * q^T)
+	let mut a = [[0.0; 2]; 2];
+	for k in 0..p.len() {
+		for i in 0..2 {
+			for j in 0..2 {
+				a[i][j] += w[k] * p[k][i] * q[k][j];
+			}
+		}
+	}
+	(a[1][0] - a[0][1]).atan2(a[0][0] + a[1][1])
+}
+
+// shape matching(Muller et al. 2005), no deformation modes
+// each particle is pulled to its goal position in XPBD form,
+// so the result does not depend on dt and iteration count
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ShapeMatchConstraint {
+	ps: Vec<usize>,
+	// rest positions relative to rest center
+	q: Vec<V2>,
+	lambda: Vec<V2>,
+	compliance: f32,
+}
+
+impl ShapeMatchConstraint {
+	pub fn new(particles: &Particles, ps: Vec<usize>) -> Self {
+		let (pos, w): (Vec<V2>, Vec<f32>) = ps
+			.iter()
+			.map(|&id| (particles.pos(id), weight(particles.imass(id))))
+			.unzip();
+		let c = center(&pos, &w);
+		let q: Vec<V2> = pos.into_iter().map(|x| x - c).collect();
+		Self {
+			ps,
+			lambda: vec![V2::zeros(); q.len()],
+			q,
+			compliance: 0.0,
+		}
+	}
+
+	pub fn with_compliance(mut self, compliance: f32) -> Self {
+		self.compliance = compliance;
+		self
+	}
+
+	pub fn build(self) -> Box<dyn Constraint> {
+		Box::new(self)
+	}
+}
+
+#[typetag::serde]
+impl Constraint for ShapeMatchConstraint {
+	fn render(&self, id: i32) -> PrConstraint {
+		PrConstraint {
+			id,
+			ty: PrConstraintType::Other,
+			particles: self.ps.clone(),
+		}
+	}
+
+	fn replace_particle(&mut self, id: usize, new_id: usize) {
+		replace_id(&mut self.ps, id, new_id);
+	}
+
+	fn is_local(&self) -> bool {
+		false
+	}
+
+	fn scale(&mut self, k: f32) {
+		for q in self.q.iter_mut() {
+			*q *= k;
+		}
+	}
+
+	fn pre_iteration(&mut self, _particles: &Particles) -> bool {
+		self.lambda.iter_mut().for_each(|x| *x = V2::zeros());
+		true
+	}
+
+	fn step(&mut self, dt: f32, particles: &Particles) {
+		let (pos, w): (Vec<V2>, Vec<f32>) = self
+			.ps
+			.iter()
+			.map(|&id| (particles.pos(id), weight(particles.imass(id))))
+			.unzip();
+		let c = center(&pos, &w);
+		let p: Vec<V2> = pos.iter().map(|x| x - c).collect();
+		let theta = fit_rotation(&p, &self.q, &w);
+		let (sin, cos) = theta.sin_cos();
+		let compliance_t = self.compliance / dt.powi(2);
+		for (k, &id) in self.ps.iter().enumerate() {
+			let imass = particles.imass(id);
+			if imass == 0.0 {
+				continue;
+			}
+			let q = self.q[k];
+			let goal =
+				c + V2::new(cos * q[0] - sin * q[1], sin * q[0] + cos * q[1]);
+			// c = pos - goal, with goal held fixed
+			let dlambda = (goal - pos[k] - self.lambda[k] * compliance_t)
+				/ (imass + compliance_t);
+			self.lambda[k] += dlambda;
+			particles.add_pos(id, dlambda * imass);
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_fit_rotation() {
+		let q = vec![V2::new(1., 0.), V2::new(0., 1.), V2::new(-1., -1.)];
+		let w = vec![1., 2., 1.];
+		let theta = 0.7f32;
+		let (sin, cos) = theta.sin_cos();
+		let p: Vec<V2> = q
+			.iter()
+			.map(|q| V2::new(cos * q[0] - sin * q[1], sin * q[0] + cos * q[1]))
+			.collect();
+		assert!((fit_rotation(&p, &q, &w) - theta).abs() < 1e-5);
+	}
+}
@@ -1,10 +1,10 @@
-use crate::constraint::particle_list::ParticleList;
-use crate::constraint::Constraint;
-use crate::particle::PRef;
+use crate::constraint::{replace_id, Constraint};
+use crate::particle::Particles;
 use crate::V2;
-use protocol::pr_model::PrConstraint;
+use protocol::pr_model::{PrConstraint, PrConstraintType};
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct VolumeConstraintTemplate {
 	pub ps: Vec<usize>,
 	pub compliance: f32,
@@ -21,26 +21,20 @@ fn area_p(p1: V2, p2: V2, p3: V2) -> f32 {
 		- p2[0] * p1[1]
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct VolumeConstraint {
-	ps: ParticleList,
-	ps_sort: ParticleList,
+	ps: [usize; 3],
 	s0: f32,
 	lambda: f32,
 	compliance: f32,
 }
 
 impl VolumeConstraint {
-	pub fn new(ps: Vec<PRef>) -> Self {
-		let ps_sort = ParticleList::new(ps.clone(), true);
-		let ps = ParticleList::new(ps, false);
-		let p0 = ps_sort[0].try_read().unwrap().get_pos();
-		let p1 = ps_sort[1].try_read().unwrap().get_pos();
-		let p2 = ps_sort[2].try_read().unwrap().get_pos();
-		let s0 = area_p(p0, p1, p2);
+	pub fn new(particles: &Particles, ps: [usize; 3]) -> Self {
+		let pos = ps.map(|id| particles.pos(id));
+		let s0 = area_p(pos[0], pos[1], pos[2]);
 		Self {
 			ps,
-			ps_sort,
 			s0,
 			lambda: 0f32,
 			compliance: 1e-9,
@@ -57,35 +51,50 @@ impl VolumeConstraint {
 	}
 }
 
+#[typetag::serde]
 impl Constraint for VolumeConstraint {
 	fn render(&self, id: i32) -> PrConstraint {
 		PrConstraint {
 			id,
-			particles: self.ps.ids(),
+			ty: PrConstraintType::Face,
+			particles: self.ps.to_vec(),
 		}
 	}
 
-	fn pre_iteration(&mut self) -> bool {
+	fn replace_particle(&mut self, id: usize, new_id: usize) {
+		replace_id(&mut self.ps, id, new_id);
+	}
+
+	fn rest(&self) -> Option<f32> {
+		Some(self.s0.abs())
+	}
+
+	fn scale(&mut self, k: f32) {
+		self.scale_rest(k * k);
+	}
+
+	fn scale_rest(&mut self, k: f32) {
+		self.s0 *= k;
+	}
+
+	fn pre_iteration(&mut self, _particles: &Particles) -> bool {
 		self.lambda = 0f32;
 		true
 	}
 
-	fn step(&mut self, dt: f32) {
-		let mut p0_mut = self.ps_sort[0].write().unwrap();
-		let mut p1_mut = self.ps_sort[1].write().unwrap();
-		let mut p2_mut = self.ps_sort[2].write().unwrap();
-
-		let imass0 = p0_mut.get_imass();
-		let imass1 = p1_mut.get_imass();
-		let imass2 = p2_mut.get_imass();
+	fn step(&mut self, dt: f32, particles: &Particles) {
+		let [id0, id1, id2] = self.ps;
+		let imass0 = particles.imass(id0);
+		let imass1 = particles.imass(id1);
+		let imass2 = particles.imass(id2);
 		let imass = imass0 + imass1 + imass2;
 		if imass == 0.0 {
 			return;
 		}
 
-		let pos0 = p0_mut.get_pos();
-		let pos1 = p1_mut.get_pos();
-		let pos2 = p2_mut.get_pos();
+		let pos0 = particles.pos(id0);
+		let pos1 = particles.pos(id1);
+		let pos2 = particles.pos(id2);
 		let s = area_p(pos0, pos1, pos2);
 		let ds = s - self.s0;
 		let x0 = pos0[0];
@@ -109,9 +118,9 @@ impl Constraint for VolumeConstraint {
 		let correct1 = dlambda * imass1 * grad1;
 		let correct2 = dlambda * imass2 * grad2;
 
-		p0_mut.add_pos(correct0);
-		p1_mut.add_pos(correct1);
-		p2_mut.add_pos(correct2);
+		particles.add_pos(id0, correct0);
+		particles.add_pos(id1, correct1);
+		particles.add_pos(id2, correct2);
 	}
 }
 
@@ -1,23 +1,181 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+
+type Map<K, V> = fnv::FnvHashMap<K, V>;
 
 use crate::constraint::leash::LeashConstraint;
 use crate::constraint::CRef;
+use crate::hierarchy::{HierarchyLevel, HierarchyTemplate};
+use crate::particle::Particles;
 use protocol::pr_model::PrConstraint;
+use serde::{Deserialize, Serialize};
 
-#[derive(Default)]
+#[derive(Default, Serialize, Deserialize)]
 pub struct ConstraintGroup {
-	id_alloc: i32,
-	constraints: HashMap<i32, CRef>,
+	// indexed by constraint id, ids are never reused,
+	// the length is the next id and is kept on restore
+	constraints: Vec<Option<CRef>>,
 	dependencies: HashMap<i32, Vec<i32>>,
 	tmp_constraints: Vec<CRef>,
-	marionette_constraints: HashMap<usize, CRef>,
+	marionette_constraints: Map<usize, CRef>,
+	levels: Vec<HierarchyLevel>,
+	// of normal and marionette constraints, invalidated when they change
+	#[serde(skip)]
+	coloring: Option<Coloring>,
+	// of collisions, rebuilt every step
+	#[serde(skip)]
+	tmp_coloring: Option<Coloring>,
+}
+
+// constraints in stable order, grouped into batches of the same color
+#[derive(Debug, PartialEq)]
+struct Coloring {
+	// index in stable order
+	order: Vec<usize>,
+	// batch i is order[bounds[i]..bounds[i + 1]]
+	bounds: Vec<usize>,
+}
+
+// first color not used by any of ids, bitset of words for each particle
+fn free_color(used: &[u64], words: usize, ids: &[usize]) -> Option<usize> {
+	for w in 0..words {
+		let mask = ids.iter().fold(0, |mask, id| mask | used[id * words + w]);
+		if mask != u64::MAX {
+			return Some(w * 64 + mask.trailing_ones() as usize);
+		}
+	}
+	None
+}
+
+// greedy coloring, constraints sharing a particle get different colors
+fn color(ps: &[Vec<usize>]) -> Coloring {
+	let len = ps.iter().flatten().max().map_or(0, |&id| id + 1);
+	let mut words = 1;
+	let mut used = vec![0u64; len];
+	let mut colors = Vec::with_capacity(ps.len());
+	let mut count = Vec::new();
+	for ids in ps.iter() {
+		let c = loop {
+			if let Some(c) = free_color(&used, words, ids) {
+				break c;
+			}
+			let mut wider = vec![0u64; len * words * 2];
+			for id in 0..len {
+				wider[id * words * 2..][..words]
+					.copy_from_slice(&used[id * words..][..words]);
+			}
+			used = wider;
+			words *= 2;
+		};
+		for id in ids.iter() {
+			used[id * words + c / 64] |= 1 << (c % 64);
+		}
+		if count.len() <= c {
+			count.resize(c + 1, 0);
+		}
+		count[c] += 1;
+		colors.push(c);
+	}
+	let mut bounds = vec![0];
+	for n in count.iter() {
+		bounds.push(bounds.last().unwrap() + n);
+	}
+	let mut offset = bounds.clone();
+	let mut order = vec![0; ps.len()];
+	for (idx, c) in colors.into_iter().enumerate() {
+		order[offset[c]] = idx;
+		offset[c] += 1;
+	}
+	Coloring { order, bounds }
+}
+
+// normal constraints by id, then marionette by particle id
+fn stable_order<'a>(
+	constraints: &'a mut [Option<CRef>],
+	marionette_constraints: &'a mut Map<usize, CRef>,
+) -> Vec<&'a mut CRef> {
+	let mut marionette: Vec<_> = marionette_constraints.iter_mut().collect();
+	marionette.sort_unstable_by_key(|(k, _)| **k);
+	constraints
+		.iter_mut()
+		.flatten()
+		.chain(marionette.into_iter().map(|(_, v)| v))
+		.collect()
+}
+
+// batches are solved in order, and constraints in a batch in parallel
+fn solve_colored(
+	constraints: Vec<&mut CRef>,
+	coloring: &mut Option<Coloring>,
+	dt: f32,
+	particles: &Particles,
+) {
+	let coloring = coloring.get_or_insert_with(|| {
+		let ps: Vec<Vec<usize>> =
+			constraints.iter().map(|c| c.render(0).particles).collect();
+		color(&ps)
+	});
+	let mut slots: Vec<Option<&mut CRef>> =
+		constraints.into_iter().map(Some).collect();
+	let mut sorted: Vec<&mut CRef> = coloring
+		.order
+		.iter()
+		.map(|&idx| slots[idx].take().unwrap())
+		.collect();
+	for bound in coloring.bounds.windows(2) {
+		let batch = &mut sorted[bound[0]..bound[1]];
+		#[cfg(not(debug_assertions))]
+		{
+			use rayon::prelude::*;
+			batch.par_iter_mut().for_each(|c| c.step(dt, particles));
+		}
+		#[cfg(debug_assertions)]
+		batch.iter_mut().for_each(|c| c.step(dt, particles));
+	}
 }
 
 impl ConstraintGroup {
 	pub fn add_constraint(&mut self, constraint: CRef) -> i32 {
-		self.constraints.insert(self.id_alloc, constraint);
-		self.id_alloc += 1;
-		self.id_alloc - 1
+		self.coloring = None;
+		self.constraints.push(Some(constraint));
+		self.constraints.len() as i32 - 1
+	}
+
+	// put back a removed constraint with its old id
+	pub fn insert_constraint(&mut self, cid: i32, constraint: CRef) {
+		self.coloring = None;
+		let slot = &mut self.constraints[cid as usize];
+		assert!(slot.is_none());
+		*slot = Some(constraint);
+	}
+
+	pub fn remove_constraint(&mut self, cid: i32) -> Option<CRef> {
+		let constraint = self.constraints.get_mut(cid as usize)?.take()?;
+		self.coloring = None;
+		Some(constraint)
+	}
+
+	// ids below are handed out, including removed ones
+	pub fn id_end(&self) -> usize {
+		self.constraints.len()
+	}
+
+	// ids below end are not handed out again
+	// e.g. after restoring a snapshot older than the last add
+	pub fn reserve_ids(&mut self, end: usize) {
+		if self.constraints.len() < end {
+			self.constraints.resize_with(end, || None);
+		}
+	}
+
+	pub fn get(&self, cid: i32) -> Option<&CRef> {
+		self.constraints.get(cid as usize)?.as_ref()
+	}
+
+	pub fn replace_particle(&mut self, cid: i32, id: usize, new_id: usize) {
+		self.coloring = None;
+		if let Some(Some(constraint)) = self.constraints.get_mut(cid as usize) {
+			constraint.replace_particle(id, new_id);
+		}
 	}
 
 	pub fn add_dependency(&mut self, key: i32, value: Vec<i32>) {
@@ -25,65 +183,236 @@ impl ConstraintGroup {
 		e.extend(value);
 	}
 
+	pub fn add_levels(
+		&mut self,
+		templates: Vec<HierarchyTemplate>,
+		id_map: &[usize],
+		cids: &[i32],
+		particles: &Particles,
+	) {
+		for (idx, template) in templates.into_iter().enumerate() {
+			if self.levels.len() <= idx {
+				self.levels.push(Default::default());
+			}
+			self.levels[idx].add_template(template, id_map, cids, particles);
+		}
+	}
+
+	// for coarse constraints along the fine edge cid
+	pub fn replace_span(&mut self, cid: i32, new: &[i32]) {
+		for level in self.levels.iter_mut() {
+			level.replace_span(cid, new);
+		}
+	}
+
+	// coarse to fine
+	pub fn solve_levels(&mut self, dt: f32, particles: &Particles) {
+		for level in self.levels.iter_mut().rev() {
+			level.solve(dt, particles);
+		}
+	}
+
 	pub fn len(&self) -> Vec<usize> {
 		vec![
-			self.constraints.len(),
+			self.constraints.iter().flatten().count(),
 			self.tmp_constraints.len(),
 			self.marionette_constraints.len(),
 		]
 	}
 
-	#[cfg(not(debug_assertions))]
-	pub fn solve_constraints(&mut self, dt: f32) {
-		use rayon::prelude::*;
-		self.constraints
-			.par_iter_mut()
-			.map(|(_k, v)| v)
-			.chain(self.tmp_constraints.par_iter_mut())
-			.chain(self.marionette_constraints.par_iter_mut().map(|(_k, v)| v))
-			.for_each(|constraint| constraint.step(dt));
+	// normal and marionette constraints, then collisions
+	// same result between runs and thread counts
+	pub fn solve_constraints(&mut self, dt: f32, particles: &Particles) {
+		let constraints = stable_order(
+			&mut self.constraints,
+			&mut self.marionette_constraints,
+		);
+		solve_colored(constraints, &mut self.coloring, dt, particles);
+		let constraints = self.tmp_constraints.iter_mut().collect();
+		solve_colored(constraints, &mut self.tmp_coloring, dt, particles);
 	}
 
-	#[cfg(debug_assertions)]
-	pub fn solve_constraints(&mut self, dt: f32) {
-		self.constraints
-			.iter_mut()
-			.map(|(_k, v)| v)
-			.chain(self.tmp_constraints.iter_mut())
-			.chain(self.marionette_constraints.values_mut())
-			.for_each(|constraint| constraint.step(dt));
+	// only collisions need velocity update for now
+	pub fn update_velocity(&mut self, particles: &mut Particles) {
+		for constraint in self.tmp_constraints.iter_mut() {
+			constraint.update_velocity(particles);
+		}
 	}
 
-	pub fn pre_iteration(&mut self) {
+	// return removed constraint ids
+	pub fn pre_iteration(&mut self, particles: &Particles) -> Vec<i32> {
 		let mut removal = vec![];
-		for (idx, constraint) in self.constraints.iter_mut() {
-			if !constraint.pre_iteration() {
-				removal.push(*idx);
+		for level in self.levels.iter_mut() {
+			level.pre_iteration(particles);
+		}
+		for (idx, constraint) in self.constraints.iter_mut().enumerate() {
+			if let Some(constraint) = constraint {
+				if !constraint.pre_iteration(particles) {
+					removal.push(idx as i32);
+				}
 			}
 		}
+		self.remove_cascade(removal)
+	}
+
+	// remove constraints and those depending on them
+	// return removed constraint ids
+	pub fn remove_cascade(&mut self, mut removal: Vec<i32>) -> Vec<i32> {
+		let mut removed = vec![];
 		while let Some(id) = removal.pop() {
-			self.constraints.remove(&id);
-			if let Some(ids) = self.dependencies.get(&id) {
+			if self.remove_constraint(id).is_none() {
+				continue;
+			}
+			removed.push(id);
+			if let Some(ids) = self.dependencies.remove(&id) {
 				removal.extend(ids);
 			}
 		}
+		if !removed.is_empty() {
+			let cids = removed.iter().copied().collect();
+			for level in self.levels.iter_mut() {
+				level.remove_constraints(&cids);
+			}
+		}
+		removed
+	}
+
+	// remove every constraint on any of the particles
+	// return removed constraint ids
+	pub fn remove_particles(&mut self, ids: &HashSet<usize>) -> Vec<i32> {
+		for id in ids.iter() {
+			self.marionette_constraints.remove(id);
+		}
+		for level in self.levels.iter_mut() {
+			level.remove_particles(ids);
+		}
+		let removal = self
+			.constraints
+			.iter()
+			.enumerate()
+			.filter_map(|(cid, c)| {
+				let ps = c.as_ref()?.render(cid as i32).particles;
+				if ps.iter().any(|id| ids.contains(id)) {
+					Some(cid as i32)
+				} else {
+					None
+				}
+			})
+			.collect();
+		self.remove_cascade(removal)
+	}
+
+	// particle is split by fracture, constraints over the whole model and
+	// coarse levels do not follow the copies, so they are dropped
+	// return removed constraint ids
+	pub fn detach_particle(&mut self, id: usize) -> Vec<i32> {
+		let ids = HashSet::from([id]);
+		for level in self.levels.iter_mut() {
+			level.remove_particles(&ids);
+		}
+		let removal = self
+			.constraints
+			.iter()
+			.enumerate()
+			.filter_map(|(cid, c)| {
+				let c = c.as_ref()?;
+				if c.is_local() || !c.render(0).particles.contains(&id) {
+					return None;
+				}
+				Some(cid as i32)
+			})
+			.collect();
+		self.remove_cascade(removal)
+	}
+
+	// uniform scaling of constraints within the particles
+	pub fn scale(&mut self, ids: &HashSet<usize>, k: f32) {
+		for (cid, c) in self.constraints.iter_mut().enumerate() {
+			if let Some(c) = c {
+				let ps = c.render(cid as i32).particles;
+				if ps.iter().all(|id| ids.contains(id)) {
+					c.scale(k);
+				}
+			}
+		}
+		for level in self.levels.iter_mut() {
+			level.scale(ids, k);
+		}
+	}
+
+	// ids of particles in any normal constraint
+	pub fn constrained_particles(&self) -> HashSet<usize> {
+		self.constraints
+			.iter()
+			.enumerate()
+			.filter_map(|(cid, c)| Some(c.as_ref()?.render(cid as i32)))
+			.flat_map(|c| c.particles)
+			.collect()
 	}
 
 	pub fn set_tmp_constraints(&mut self, tmp_constraints: Vec<CRef>) {
+		self.tmp_coloring = None;
 		self.tmp_constraints = tmp_constraints;
 	}
 
 	pub fn control_particle(&mut self, id: usize, con: LeashConstraint) {
+		self.coloring = None;
 		self.marionette_constraints.insert(id, Box::new(con));
 	}
 
 	pub fn uncontrol_particle(&mut self, id: usize) {
+		self.coloring = None;
 		self.marionette_constraints.remove(&id);
 	}
 
 	pub fn pr_constraints(&self) -> Vec<PrConstraint> {
 		// NOTE: since only normal constraint has id
 		// more consideration is needed for rendering special constraints
-		self.constraints.iter().map(|(v, k)| k.render(*v)).collect()
+		self.constraints
+			.iter()
+			.enumerate()
+			.filter_map(|(id, c)| Some(c.as_ref()?.render(id as i32)))
+			.collect()
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_color() {
+		let ps = vec![vec![0, 1], vec![1, 2], vec![2, 3], vec![4], vec![0, 2]];
+		let coloring = color(&ps);
+		assert_eq!(
+			coloring,
+			Coloring {
+				order: vec![0, 2, 3, 1, 4],
+				bounds: vec![0, 3, 4, 5],
+			}
+		);
+	}
+
+	// coloring is kept between steps until constraints change
+	#[test]
+	fn test_coloring_cache() {
+		use crate::constraint::distance::DistanceConstraint;
+		use crate::particle::Particle;
+		use crate::V2;
+		let mut particles = Particles::default();
+		for x in 0..3 {
+			let pos = V2::new(x as f32, 0.0);
+			particles.add(Particle::new(1.0, pos, V2::zeros()));
+		}
+		let mut cg = ConstraintGroup::default();
+		cg.add_constraint(DistanceConstraint::new(&particles, 0, 1).build());
+		let cid = cg
+			.add_constraint(DistanceConstraint::new(&particles, 1, 2).build());
+		cg.solve_constraints(0.01, &particles);
+		cg.pre_iteration(&particles);
+		cg.set_tmp_constraints(Vec::new());
+		assert!(cg.coloring.is_some());
+		cg.remove_constraint(cid);
+		assert!(cg.coloring.is_none());
 	}
 }
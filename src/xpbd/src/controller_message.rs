@@ -1,6 +1,30 @@
+use crate::physical_model::PhysicalModel;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
 pub enum ControllerMessage {
 	TogglePause,
 	FrameForward,
 	ControlParticle(usize, [f32; 2]),
 	UncontrolParticle(usize),
+	SetGravity([f32; 2]),
+	// model, offset, payload(e.g. encoded texture data)
+	// payload is opaque to the world, it is recorded with the message
+	// and sent back with UserEvent::ModelAdded
+	AddModel(PhysicalModel, [f32; 2], Vec<u8>),
+	RemoveParticle(usize),
+	RemoveConstraint(i32),
+	// model group of add_model order, from 1
+	RemoveModel(usize),
+	RemoveOrphans,
+	Clear,
+	// restore frame from history and pause
+	Rewind(usize),
+	// move n stored frames in history and pause
+	Scrub(i32),
+	// restore frame from history and play, later frames are replaced
+	ResumeFrom(usize),
+	// snapshot file path
+	SaveSnapshot(String),
+	LoadSnapshot(String),
 }
@@ -0,0 +1,59 @@
+use crate::particle::{ParticleTemplate, ParticleType, Surface};
+use crate::physical_model::PhysicalModel;
+use crate::V2;
+
+pub struct FluidModelBuilder {
+	// also particle radius, rest density is computed from it,
+	// less than the kernel radius(ParticleGroup csize)
+	spacing: f32,
+	imass: f32,
+	surface: Surface,
+	particles: Vec<ParticleTemplate>,
+}
+
+impl Default for FluidModelBuilder {
+	fn default() -> Self {
+		Self {
+			spacing: 0.04,
+			imass: 1.0,
+			surface: Default::default(),
+			particles: Vec::new(),
+		}
+	}
+}
+
+impl FluidModelBuilder {
+	pub fn with_imass(mut self, imass: f32) -> Self {
+		self.imass = imass;
+		self
+	}
+
+	pub fn with_surface(mut self, surface: Surface) -> Self {
+		self.surface = surface;
+		self
+	}
+
+	// fill a rectangle of size[0] x size[1] particles at offset
+	pub fn add_rect(&mut self, offset: V2, size: [usize; 2]) {
+		for idx in 0..size[0] {
+			for idy in 0..size[1] {
+				let pos =
+					offset + V2::new(idx as f32, idy as f32) * self.spacing;
+				self.particles.push(ParticleTemplate {
+					imass: self.imass,
+					pos,
+					radius: self.spacing,
+					ty: ParticleType::Fluid,
+					surface: self.surface,
+				});
+			}
+		}
+	}
+
+	pub fn build_physical_model(&mut self) -> PhysicalModel {
+		PhysicalModel {
+			particles: std::mem::take(&mut self.particles),
+			..Default::default()
+		}
+	}
+}
@@ -0,0 +1,167 @@
+use crate::particle::Particles;
+use crate::V2;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Field {
+	// constant acceleration, in addition to world gravity
+	Gravity(V2),
+	// force -(linear + quadratic * |v|) * v
+	Drag {
+		linear: f32,
+		quadratic: f32,
+	},
+	// drag towards the wind velocity, gusts vary by noise(0: steady)
+	Wind {
+		velocity: V2,
+		drag: f32,
+		noise: f32,
+	},
+	// acceleration towards center fading to 0 at radius
+	// negative strength repels
+	Attractor {
+		center: V2,
+		strength: f32,
+		radius: f32,
+	},
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Target {
+	All,
+	// model groups, numbered from 1 in the order of add_model
+	Models(Vec<usize>),
+	// particles with any of the collision layer bits
+	Layers(u32),
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ForceField {
+	pub field: Field,
+	pub target: Target,
+}
+
+// smooth pseudo random value in [-1, 1], deterministic in t and pos
+fn gust(t: f32, pos: V2, seed: f32) -> f32 {
+	(1.3 * t + 0.7 * pos[0] + seed).sin()
+		* (0.9 * t + 1.1 * pos[1] + 1.7 * seed).sin()
+}
+
+// drag can stop relative motion in one step, not reverse it
+fn limit_drag(a: V2, dv: V2, dt: f32) -> V2 {
+	let l = a.magnitude() * dt;
+	if l > dv.magnitude() {
+		a * dv.magnitude() / l
+	} else {
+		a
+	}
+}
+
+impl ForceField {
+	pub fn new(field: Field) -> Self {
+		Self {
+			field,
+			target: Target::All,
+		}
+	}
+
+	pub fn with_target(mut self, target: Target) -> Self {
+		self.target = target;
+		self
+	}
+
+	pub fn targets(&self, particles: &Particles, id: usize) -> bool {
+		match &self.target {
+			Target::All => true,
+			Target::Models(groups) => groups.contains(&particles.group(id)),
+			Target::Layers(layers) => particles.filter(id).layer & layers != 0,
+		}
+	}
+
+	// t: world time, dt: step size
+	pub fn accel(
+		&self,
+		particles: &Particles,
+		id: usize,
+		t: f32,
+		dt: f32,
+	) -> V2 {
+		let pos = particles.pos(id);
+		let pdt = particles.pdt(id);
+		let v = if pdt == 0f32 {
+			V2::new(0.0, 0.0)
+		} else {
+			particles.dpos(id) / pdt
+		};
+		let imass = particles.imass(id);
+		match &self.field {
+			Field::Gravity(g) => *g,
+			Field::Drag { linear, quadratic } => {
+				let a = -(linear + quadratic * v.magnitude()) * v * imass;
+				limit_drag(a, v, dt)
+			}
+			Field::Wind {
+				velocity,
+				drag,
+				noise,
+			} => {
+				let perp = V2::new(-velocity[1], velocity[0]);
+				let wind = velocity * (1.0 + noise * gust(t, pos, 0.0))
+					+ perp * *noise * gust(t, pos, 2.3);
+				limit_drag((wind - v) * *drag * imass, wind - v, dt)
+			}
+			Field::Attractor {
+				center,
+				strength,
+				radius,
+			} => {
+				let dp = center - pos;
+				let r = dp.magnitude();
+				if r == 0.0 || r >= *radius {
+					return V2::new(0.0, 0.0);
+				}
+				dp / r * *strength * (1.0 - r / radius)
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::particle::Particle;
+
+	#[test]
+	fn test_attractor() {
+		let field = ForceField::new(Field::Attractor {
+			center: V2::new(0., 0.),
+			strength: 4.0,
+			radius: 1.0,
+		})
+		.with_target(Target::Models(vec![1]));
+		let mut particles = Particles::default();
+		let mut add = |x: f32, group: usize| {
+			let mut p = Particle::new(1.0, V2::new(x, 0.), V2::zeros());
+			p.group = group;
+			particles.add(p)
+		};
+		let inside = add(0.5, 1);
+		let outside = add(2.0, 1);
+		let untargeted = add(0.5, 2);
+		let dt = 0.01;
+		for id in [inside, outside, untargeted] {
+			let accel = if field.targets(&particles, id) {
+				field.accel(&particles, id, 0.0, dt)
+			} else {
+				V2::zeros()
+			};
+			particles.set_accel(id, accel);
+			particles.update(id, dt, f32::INFINITY);
+		}
+		// from rest, one step moves by accel * dt^2
+		let accel = |id| particles.dpos(id) / (dt * dt);
+		assert!((accel(inside) - V2::new(-2.0, 0.)).magnitude() < 1e-3);
+		assert_eq!(accel(outside), V2::zeros());
+		assert_eq!(accel(untargeted), V2::zeros());
+	}
+}
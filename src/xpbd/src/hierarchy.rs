@@ -0,0 +1,175 @@
+use std::collections::HashSet;
+
+use crate::constraint::constraint_template::ConstraintTemplate;
+use crate::constraint::CRef;
+use crate::particle::Particles;
+use crate::V2;
+use serde::{Deserialize, Serialize};
+
+// one coarse level of a model, particle idx are model particle idx
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct HierarchyTemplate {
+	pub coarse: Vec<usize>,
+	pub constraints: Vec<ConstraintTemplate>,
+	// model constraint idx of the fine edges along each coarse constraint
+	pub spans: Vec<Vec<usize>>,
+	// fine particle idx, [(idx in coarse, weight)]
+	pub children: Vec<(usize, Vec<(usize, f32)>)>,
+}
+
+// coarse constraints are solved first,
+// then the correction of coarse particles is interpolated to children
+// a coarse constraint is dropped when a fine edge along it is removed
+#[derive(Default, Serialize, Deserialize)]
+pub struct HierarchyLevel {
+	coarse: Vec<usize>,
+	// with constraint ids of the fine edges along it
+	constraints: Vec<(CRef, Vec<i32>)>,
+	children: Vec<(usize, Vec<(usize, f32)>)>,
+}
+
+impl HierarchyLevel {
+	pub fn add_template(
+		&mut self,
+		template: HierarchyTemplate,
+		id_map: &[usize],
+		cids: &[i32],
+		particles: &Particles,
+	) {
+		let offset = self.coarse.len();
+		self.coarse
+			.extend(template.coarse.iter().map(|&idx| id_map[idx]));
+		self.constraints.extend(
+			template.constraints.into_iter().zip(template.spans).map(
+				|(c, span)| {
+					let span = span.into_iter().map(|idx| cids[idx]).collect();
+					(c.instantiate(id_map, particles), span)
+				},
+			),
+		);
+		self.children.extend(template.children.into_iter().map(
+			|(idx, weights)| {
+				let weights =
+					weights.into_iter().map(|(i, w)| (i + offset, w)).collect();
+				(id_map[idx], weights)
+			},
+		));
+	}
+
+	// drop coarse constraints on removed particles,
+	// children follow the remaining coarse particles
+	pub fn remove_particles(&mut self, ids: &HashSet<usize>) {
+		self.constraints.retain(|(c, _)| {
+			!c.render(0).particles.iter().any(|id| ids.contains(id))
+		});
+		let removed: HashSet<usize> = (0..self.coarse.len())
+			.filter(|&i| ids.contains(&self.coarse[i]))
+			.collect();
+		self.children.retain_mut(|(id, weights)| {
+			if ids.contains(id) {
+				return false;
+			}
+			weights.retain(|(i, _)| !removed.contains(i));
+			let sum: f32 = weights.iter().map(|x| x.1).sum();
+			weights.iter_mut().for_each(|x| x.1 /= sum);
+			sum > 0.0
+		});
+	}
+
+	// drop coarse constraints along removed fine edges, e.g. broken ones
+	pub fn remove_constraints(&mut self, cids: &HashSet<i32>) {
+		self.constraints
+			.retain(|(_, span)| !span.iter().any(|cid| cids.contains(cid)));
+	}
+
+	// fine edge cid is replaced by new ones covering the same path,
+	// e.g. split or merged by remeshing
+	pub fn replace_span(&mut self, cid: i32, new: &[i32]) {
+		for (_, span) in self.constraints.iter_mut() {
+			if !span.contains(&cid) {
+				continue;
+			}
+			span.retain(|&x| x != cid);
+			for &x in new.iter() {
+				if !span.contains(&x) {
+					span.push(x);
+				}
+			}
+		}
+	}
+
+	pub fn scale(&mut self, ids: &HashSet<usize>, k: f32) {
+		for (c, _) in self.constraints.iter_mut() {
+			if c.render(0).particles.iter().all(|id| ids.contains(id)) {
+				c.scale(k);
+			}
+		}
+	}
+
+	pub fn pre_iteration(&mut self, particles: &Particles) {
+		for (constraint, _) in self.constraints.iter_mut() {
+			constraint.pre_iteration(particles);
+		}
+	}
+
+	pub fn solve(&mut self, dt: f32, particles: &Particles) {
+		let pos0: Vec<V2> =
+			self.coarse.iter().map(|&id| particles.pos(id)).collect();
+		for (constraint, _) in self.constraints.iter_mut() {
+			constraint.step(dt, particles);
+		}
+		let dps: Vec<V2> = self
+			.coarse
+			.iter()
+			.zip(pos0)
+			.map(|(&id, pos0)| particles.pos(id) - pos0)
+			.collect();
+		for &(id, ref weights) in self.children.iter() {
+			if particles.imass(id) == 0.0 {
+				continue;
+			}
+			let dp = weights
+				.iter()
+				.fold(V2::new(0., 0.), |dp, &(i, w)| dp + dps[i] * w);
+			particles.add_pos(id, dp);
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::constraint::distance::{
+		DistanceConstraintTemplate, DistanceConstraintType,
+	};
+
+	#[test]
+	fn test_span() {
+		let template = HierarchyTemplate {
+			coarse: vec![0, 2],
+			constraints: vec![ConstraintTemplate::Distance(
+				DistanceConstraintTemplate {
+					ps: vec![0, 2],
+					l0: 2.0,
+					compliance: 0.0,
+					ty: DistanceConstraintType::Attractive,
+					break_range: None,
+				},
+			)],
+			spans: vec![vec![0, 1]],
+			children: vec![(1, vec![(0, 0.5), (1, 0.5)])],
+		};
+		let mut level = HierarchyLevel::default();
+		level.add_template(
+			template,
+			&[0, 1, 2],
+			&[5, 6],
+			&Particles::default(),
+		);
+		// fine edge 5 is split into 7 and 8
+		level.replace_span(5, &[7, 8]);
+		assert_eq!(level.constraints[0].1, vec![6, 7, 8]);
+		level.remove_constraints(&[6].into_iter().collect());
+		assert!(level.constraints.is_empty());
+	}
+}
@@ -0,0 +1,81 @@
+use std::collections::VecDeque;
+
+// bounded ring buffer of world snapshots, oldest are dropped first
+pub struct History {
+	// snapshot is taken every interval frames
+	pub interval: usize,
+	pub capacity: usize,
+	// frame index, snapshot, in frame order
+	frames: VecDeque<(usize, Vec<u8>)>,
+}
+
+impl History {
+	pub fn new(interval: usize, capacity: usize) -> Self {
+		Self {
+			interval: interval.max(1),
+			capacity,
+			frames: VecDeque::new(),
+		}
+	}
+
+	pub fn due(&self, frame: usize) -> bool {
+		frame.is_multiple_of(self.interval)
+	}
+
+	// frames after this one belong to an abandoned run and are dropped
+	pub fn record(&mut self, frame: usize, snapshot: Vec<u8>) {
+		while matches!(self.frames.back(), Some((f, _)) if *f >= frame) {
+			self.frames.pop_back();
+		}
+		self.frames.push_back((frame, snapshot));
+		while self.frames.len() > self.capacity {
+			self.frames.pop_front();
+		}
+	}
+
+	pub fn get(&self, frame: usize) -> Option<&[u8]> {
+		let idx = self.frames.binary_search_by_key(&frame, |x| x.0).ok()?;
+		Some(&self.frames[idx].1)
+	}
+
+	// first and last stored frame
+	pub fn range(&self) -> Option<[usize; 2]> {
+		Some([self.frames.front()?.0, self.frames.back()?.0])
+	}
+
+	// stored frame n steps away from frame, clamped to the stored range
+	// a frame between two stored ones counts as just after the earlier
+	pub fn scrub(&self, frame: usize, n: i32) -> Option<usize> {
+		if self.frames.is_empty() {
+			return None;
+		}
+		let idx = match self.frames.binary_search_by_key(&frame, |x| x.0) {
+			Ok(idx) => idx as i32 + n,
+			Err(idx) if n < 0 => idx as i32 + n,
+			Err(idx) => idx as i32 + n - 1,
+		};
+		let idx = idx.clamp(0, self.frames.len() as i32 - 1);
+		Some(self.frames[idx as usize].0)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_scrub() {
+		let mut history = History::new(10, 3);
+		for frame in [0, 10, 20, 30] {
+			history.record(frame, Vec::new());
+		}
+		assert_eq!(history.range(), Some([10, 30]));
+		assert_eq!(history.scrub(30, -1), Some(20));
+		assert_eq!(history.scrub(25, -1), Some(20));
+		assert_eq!(history.scrub(25, 1), Some(30));
+		assert_eq!(history.scrub(20, -5), Some(10));
+		// continuing from frame 10 drops later frames
+		history.record(10, Vec::new());
+		assert_eq!(history.range(), Some([10, 10]));
+	}
+}
@@ -1,11 +1,20 @@
 pub mod constraint;
 mod constraint_group;
 pub mod controller_message;
+pub mod fluid_model;
+pub mod force_field;
+pub mod hierarchy;
+pub mod history;
+pub mod model_handle;
+pub mod obstacle;
 pub mod particle;
 mod particle_group;
 pub mod physical_model;
 pub mod posbox;
 pub mod pworld;
+pub mod recorder;
+pub mod remesh;
+mod topology;
 
 pub type V2 = nalgebra::Vector2<f32>;
 pub type C2 = nalgebra::Vector2<i32>;
@@ -0,0 +1,62 @@
+use crate::pworld::PWorld;
+use crate::V2;
+
+// a model added to PWorld
+// fields are ids at the time of adding, particles and constraints
+// created later(e.g. by remeshing) are found by group, so operations
+// apply to the whole model
+#[derive(Clone, Debug)]
+pub struct ModelHandle {
+	// particle group of the model
+	pub group: usize,
+	// model particle idx -> particle id
+	pub particles: Vec<usize>,
+	// model constraint idx -> constraint id
+	pub constraints: Vec<i32>,
+	// ids of face(volume) constraints
+	pub faces: Vec<i32>,
+}
+
+impl ModelHandle {
+	// current ids, including those created after adding
+	pub fn particle_ids(&self, pworld: &PWorld) -> Vec<usize> {
+		pworld.model_particles(self.group)
+	}
+
+	pub fn constraint_ids(&self, pworld: &PWorld) -> Vec<i32> {
+		pworld.model_constraints(self.group)
+	}
+
+	pub fn aabb(&self, pworld: &PWorld) -> Option<[V2; 2]> {
+		pworld.model_aabb(self.group)
+	}
+
+	pub fn center(&self, pworld: &PWorld) -> Option<V2> {
+		pworld.model_center(self.group)
+	}
+
+	pub fn velocity(&self, pworld: &PWorld) -> Option<V2> {
+		pworld.model_velocity(self.group)
+	}
+
+	pub fn translate(&self, pworld: &mut PWorld, d: V2) {
+		pworld.translate_model(self.group, d);
+	}
+
+	pub fn rotate(&self, pworld: &mut PWorld, angle: f32) {
+		pworld.rotate_model(self.group, angle);
+	}
+
+	pub fn scale(&self, pworld: &mut PWorld, k: f32) {
+		pworld.scale_model(self.group, k);
+	}
+
+	pub fn impulse(&self, pworld: &mut PWorld, dv: V2) {
+		pworld.impulse_model(self.group, dv);
+	}
+
+	// return removed constraint ids
+	pub fn remove(&self, pworld: &mut PWorld) -> Vec<i32> {
+		pworld.remove_model(self.group)
+	}
+}
@@ -0,0 +1,318 @@
+use crate::particle::Surface;
+use crate::V2;
+use serde::{Deserialize, Serialize};
+
+// signed distance field sampled at cell centers, negative inside
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Sdf {
+	origin: V2,
+	cell: f32,
+	size: [usize; 2],
+	data: Vec<f32>,
+}
+
+// distance from each cell center to the nearest seed cell center
+// two pass propagation of nearest seeds(8SSEDT)
+fn distance_transform(seed: &[bool], size: [usize; 2]) -> Vec<f32> {
+	let [w, h] = size;
+	let far = (w + h) as f32 * 2.0;
+	let mut nearest: Vec<Option<V2>> = (0..w * h)
+		.map(|i| {
+			if seed[i] {
+				Some(V2::new((i / h) as f32, (i % h) as f32))
+			} else {
+				None
+			}
+		})
+		.collect();
+	let dist = |i: usize, s: Option<V2>| match s {
+		Some(s) => (V2::new((i / h) as f32, (i % h) as f32) - s).magnitude(),
+		None => far,
+	};
+	let mut pass = |order: &mut dyn Iterator<Item = usize>,
+	                dirs: &[[i32; 2]]| {
+		for i in order {
+			let (x, y) = ((i / h) as i32, (i % h) as i32);
+			for [dx, dy] in dirs.iter() {
+				let (nx, ny) = (x + dx, y + dy);
+				if nx < 0 || ny < 0 || nx >= w as i32 || ny >= h as i32 {
+					continue;
+				}
+				let s = nearest[nx as usize * h + ny as usize];
+				if dist(i, s) < dist(i, nearest[i]) {
+					nearest[i] = s;
+				}
+			}
+		}
+	};
+	pass(&mut (0..w * h), &[[-1, -1], [-1, 0], [-1, 1], [0, -1]]);
+	pass(&mut (0..w * h).rev(), &[[1, 1], [1, 0], [1, -1], [0, 1]]);
+	(0..w * h).map(|i| dist(i, nearest[i])).collect()
+}
+
+impl Sdf {
+	// mask[x][y]: cell is solid, cell x covers origin + x * cell
+	pub fn new(mask: &[Vec<bool>], origin: V2, cell: f32) -> Self {
+		let size = [mask.len(), mask[0].len()];
+		let inside: Vec<bool> = mask.iter().flatten().copied().collect();
+		let outside: Vec<bool> = inside.iter().map(|x| !x).collect();
+		let to_inside = distance_transform(&inside, size);
+		let to_outside = distance_transform(&outside, size);
+		// boundary is half a cell from centers
+		let data = (0..inside.len())
+			.map(|i| {
+				if inside[i] {
+					(0.5 - to_outside[i]) * cell
+				} else {
+					(to_inside[i] - 0.5) * cell
+				}
+			})
+			.collect();
+		Self {
+			origin,
+			cell,
+			size,
+			data,
+		}
+	}
+
+	fn get(&self, x: usize, y: usize) -> f32 {
+		self.data[x * self.size[1] + y]
+	}
+
+	// bilinear, distance to the grid is added outside of it
+	pub fn sample(&self, p: V2) -> f32 {
+		let g = (p - self.origin) / self.cell - V2::new(0.5, 0.5);
+		let max = V2::new(self.size[0] as f32 - 1.0, self.size[1] as f32 - 1.0);
+		let c = g.sup(&V2::new(0.0, 0.0)).inf(&max);
+		let outside = (g - c).magnitude() * self.cell;
+		let (x0, y0) = (c[0].floor() as usize, c[1].floor() as usize);
+		let x1 = (x0 + 1).min(self.size[0] - 1);
+		let y1 = (y0 + 1).min(self.size[1] - 1);
+		let (tx, ty) = (c[0] - x0 as f32, c[1] - y0 as f32);
+		let d0 = self.get(x0, y0) * (1.0 - tx) + self.get(x1, y0) * tx;
+		let d1 = self.get(x0, y1) * (1.0 - tx) + self.get(x1, y1) * tx;
+		d0 * (1.0 - ty) + d1 * ty + outside
+	}
+
+	fn is_inside(&self, x: i32, y: i32) -> bool {
+		if x < 0 || y < 0 {
+			return false;
+		}
+		let (x, y) = (x as usize, y as usize);
+		x < self.size[0] && y < self.size[1] && self.get(x, y) < 0.0
+	}
+
+	// outline of solid cells
+	fn lines(&self) -> Vec<[V2; 2]> {
+		let mut result = Vec::new();
+		for x in 0..self.size[0] as i32 {
+			for y in 0..self.size[1] as i32 {
+				if !self.is_inside(x, y) {
+					continue;
+				}
+				let corner = |dx: i32, dy: i32| {
+					self.origin
+						+ V2::new((x + dx) as f32, (y + dy) as f32) * self.cell
+				};
+				if !self.is_inside(x - 1, y) {
+					result.push([corner(0, 0), corner(0, 1)]);
+				}
+				if !self.is_inside(x + 1, y) {
+					result.push([corner(1, 0), corner(1, 1)]);
+				}
+				if !self.is_inside(x, y - 1) {
+					result.push([corner(0, 0), corner(1, 0)]);
+				}
+				if !self.is_inside(x, y + 1) {
+					result.push([corner(0, 1), corner(1, 1)]);
+				}
+			}
+		}
+		result
+	}
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub enum Shape {
+	Segment([V2; 2]),
+	// convex, either orientation
+	Polygon(Vec<V2>),
+	Circle(V2, f32),
+	Sdf(Sdf),
+}
+
+fn closest_on_segment(p: V2, a: V2, b: V2) -> V2 {
+	let e = b - a;
+	let l2 = e.magnitude_squared();
+	if l2 == 0.0 {
+		return a;
+	}
+	a + e * ((p - a).dot(&e) / l2).clamp(0.0, 1.0)
+}
+
+// static collision geometry
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Obstacle {
+	pub shape: Shape,
+	#[serde(default)]
+	pub surface: Surface,
+}
+
+impl Obstacle {
+	pub fn new(shape: Shape) -> Self {
+		Self {
+			shape,
+			surface: Default::default(),
+		}
+	}
+
+	pub fn with_surface(mut self, surface: Surface) -> Self {
+		self.surface = surface;
+		self
+	}
+
+	// signed distance and outward normal at p
+	// None if the normal is undefined
+	pub fn distance(&self, p: V2) -> Option<(f32, V2)> {
+		match &self.shape {
+			Shape::Segment([a, b]) => {
+				let dp = p - closest_on_segment(p, *a, *b);
+				let l = dp.magnitude();
+				if l == 0.0 {
+					return None;
+				}
+				Some((l, dp / l))
+			}
+			Shape::Polygon(vs) => {
+				let n = vs.len();
+				// shoelace, sign decides which side is outward
+				let area: f32 =
+					(0..n).map(|i| vs[i].perp(&vs[(i + 1) % n])).sum::<f32>();
+				let mut max_plane = (f32::NEG_INFINITY, V2::new(0.0, 0.0));
+				let mut min_dist = (f32::INFINITY, V2::new(0.0, 0.0));
+				for i in 0..n {
+					let (a, b) = (vs[i], vs[(i + 1) % n]);
+					let e = b - a;
+					let normal =
+						V2::new(e[1], -e[0]).normalize() * area.signum();
+					let d = (p - a).dot(&normal);
+					if d > max_plane.0 {
+						max_plane = (d, normal);
+					}
+					let dp = p - closest_on_segment(p, a, b);
+					if dp.magnitude() < min_dist.0 {
+						min_dist = (dp.magnitude(), dp);
+					}
+				}
+				if max_plane.0 <= 0.0 {
+					return Some(max_plane);
+				}
+				Some((min_dist.0, min_dist.1 / min_dist.0))
+			}
+			Shape::Circle(c, r) => {
+				let dp = p - c;
+				let l = dp.magnitude();
+				if l == 0.0 {
+					return None;
+				}
+				Some((l - r, dp / l))
+			}
+			Shape::Sdf(sdf) => {
+				let d = sdf.sample(p);
+				let eps = sdf.cell / 2.0;
+				let grad = V2::new(
+					sdf.sample(p + V2::new(eps, 0.0))
+						- sdf.sample(p - V2::new(eps, 0.0)),
+					sdf.sample(p + V2::new(0.0, eps))
+						- sdf.sample(p - V2::new(0.0, eps)),
+				);
+				let l = grad.magnitude();
+				if l == 0.0 {
+					return None;
+				}
+				Some((d, grad / l))
+			}
+		}
+	}
+
+	// bounding box [min, max]
+	pub fn aabb(&self) -> [V2; 2] {
+		let bound = |vs: &[V2]| {
+			let min = vs.iter().fold(vs[0], |m, v| m.inf(v));
+			let max = vs.iter().fold(vs[0], |m, v| m.sup(v));
+			[min, max]
+		};
+		match &self.shape {
+			Shape::Segment(vs) => bound(vs),
+			Shape::Polygon(vs) => bound(vs),
+			Shape::Circle(c, r) => [c - V2::new(*r, *r), c + V2::new(*r, *r)],
+			Shape::Sdf(sdf) => {
+				let size = V2::new(sdf.size[0] as f32, sdf.size[1] as f32);
+				[sdf.origin, sdf.origin + size * sdf.cell]
+			}
+		}
+	}
+
+	// wireframe for rendering
+	pub fn lines(&self) -> Vec<[V2; 2]> {
+		let loop_lines = |vs: &[V2]| {
+			(0..vs.len())
+				.map(|i| [vs[i], vs[(i + 1) % vs.len()]])
+				.collect()
+		};
+		match &self.shape {
+			Shape::Segment(vs) => vec![*vs],
+			Shape::Polygon(vs) => loop_lines(vs),
+			Shape::Circle(c, r) => {
+				let vs: Vec<V2> = (0..32)
+					.map(|i| {
+						let (sin, cos) =
+							(i as f32 / 32.0 * std::f32::consts::TAU).sin_cos();
+						c + V2::new(cos, sin) * *r
+					})
+					.collect();
+				loop_lines(&vs)
+			}
+			Shape::Sdf(sdf) => sdf.lines(),
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_distance() {
+		let square = vec![
+			V2::new(0., 0.),
+			V2::new(0., 2.),
+			V2::new(2., 2.),
+			V2::new(2., 0.),
+		];
+		let polygon = Obstacle::new(Shape::Polygon(square));
+		let (d, n) = polygon.distance(V2::new(1.5, 1.0)).unwrap();
+		assert!((d + 0.5).abs() < 1e-5);
+		assert!((n - V2::new(1., 0.)).magnitude() < 1e-5);
+		let (d, _) = polygon.distance(V2::new(3., 3.)).unwrap();
+		assert!((d - 2f32.sqrt()).abs() < 1e-5);
+
+		// solid square of 10 x 10 cells at 5..15
+		let mask: Vec<Vec<bool>> = (0..20)
+			.map(|x| {
+				(0..20)
+					.map(|y| (5..15).contains(&x) && (5..15).contains(&y))
+					.collect()
+			})
+			.collect();
+		let sdf =
+			Obstacle::new(Shape::Sdf(Sdf::new(&mask, V2::new(0., 0.), 0.1)));
+		let (d, n) = sdf.distance(V2::new(1.7, 1.0)).unwrap();
+		assert!((d - 0.2).abs() < 0.02);
+		assert!((n - V2::new(1., 0.)).magnitude() < 0.05);
+		let (d, n) = sdf.distance(V2::new(1.0, 0.8)).unwrap();
+		assert!((d + 0.3).abs() < 0.02);
+		assert!((n - V2::new(0., -1.)).magnitude() < 0.05);
+	}
+}
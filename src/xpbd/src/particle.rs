@@ -1,77 +1,429 @@
-use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicU64, Ordering};
 
 use crate::V2;
 use protocol::pr_model::PrParticle;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
-pub type PRef = Arc<RwLock<Particle>>;
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ParticleType {
+	Solid,
+	Fluid,
+}
 
-#[derive(Clone)]
+// contact properties
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Surface {
+	pub static_friction: f32,
+	pub kinetic_friction: f32,
+	pub restitution: f32,
+}
+
+impl Default for Surface {
+	fn default() -> Self {
+		Self {
+			static_friction: 0.4,
+			kinetic_friction: 0.3,
+			restitution: 0.0,
+		}
+	}
+}
+
+impl Surface {
+	// combined surface of two contacting particles
+	pub fn mix(&self, other: &Surface) -> Surface {
+		Self {
+			static_friction: (self.static_friction + other.static_friction)
+				/ 2.0,
+			kinetic_friction: (self.kinetic_friction + other.kinetic_friction)
+				/ 2.0,
+			restitution: (self.restitution + other.restitution) / 2.0,
+		}
+	}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum SelfCollision {
+	All,
+	None,
+	// skip pairs joined by a constraint of the model
+	Unconnected,
+}
+
+// two particles collide if each one's layer is in the other's mask
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct CollisionFilter {
+	pub layer: u32,
+	pub mask: u32,
+	pub self_collision: SelfCollision,
+}
+
+impl Default for CollisionFilter {
+	fn default() -> Self {
+		Self {
+			layer: 1,
+			mask: u32::MAX,
+			self_collision: SelfCollision::All,
+		}
+	}
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct ParticleTemplate {
 	pub imass: f32,
 	pub pos: V2,
+	// contact distance of two particles is the sum of radii
+	pub radius: f32,
+	pub ty: ParticleType,
+	pub surface: Surface,
 }
 
+impl ParticleTemplate {
+	// solid particle with default surface
+	pub fn new(imass: f32, pos: V2, radius: f32) -> Self {
+		Self {
+			imass,
+			pos,
+			radius,
+			ty: ParticleType::Solid,
+			surface: Default::default(),
+		}
+	}
+}
+
+// one particle, used for adding and copying
 #[derive(Clone)]
 pub struct Particle {
-	pub id: usize, // prevent dead lock
 	pub imass: f32,
 	pub pos: V2,
+	pub radius: f32,
 	pub ppos: V2,
+	// step size of last update, 0: not updated yet
+	pub pdt: f32,
 	pub accel: V2,
+	pub ty: ParticleType,
+	pub surface: Surface,
+	pub filter: CollisionFilter,
+	// model the particle belongs to, for self collision
+	pub group: usize,
 }
 
 impl Particle {
-	pub fn new_ref(id: usize, imass: f32, pos: V2, accel: V2) -> PRef {
-		let result = Self {
-			id,
+	pub fn new(imass: f32, pos: V2, accel: V2) -> Self {
+		Self {
 			imass, // inf is handled
 			pos,
+			radius: 0.04,
 			ppos: pos,
+			pdt: 0f32,
 			accel,
-		};
-		Arc::new(RwLock::new(result))
+			ty: ParticleType::Solid,
+			surface: Default::default(),
+			filter: Default::default(),
+			group: 0,
+		}
 	}
+}
+
+// position written by constraints solved in parallel
+// relaxed load and store are plain moves, no locking
+#[derive(Default)]
+struct AtomicV2(AtomicU64);
+
+impl AtomicV2 {
+	fn new(v: V2) -> Self {
+		let result = Self::default();
+		result.store(v);
+		result
+	}
+
+	fn load(&self) -> V2 {
+		let bits = self.0.load(Ordering::Relaxed);
+		V2::new(
+			f32::from_bits(bits as u32),
+			f32::from_bits((bits >> 32) as u32),
+		)
+	}
+
+	fn store(&self, v: V2) {
+		let bits = v[0].to_bits() as u64 | (v[1].to_bits() as u64) << 32;
+		self.0.store(bits, Ordering::Relaxed);
+	}
+}
+
+impl Serialize for AtomicV2 {
+	fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+		self.load().serialize(s)
+	}
+}
+
+impl<'de> Deserialize<'de> for AtomicV2 {
+	fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+		V2::deserialize(d).map(Self::new)
+	}
+}
+
+// struct of arrays indexed by particle id, ids are never reused
+#[derive(Default, Serialize, Deserialize)]
+pub struct Particles {
+	pos: Vec<AtomicV2>,
+	radius: Vec<f32>,
+	ppos: Vec<V2>,
+	pdt: Vec<f32>,
+	imass: Vec<f32>,
+	accel: Vec<V2>,
+	ty: Vec<ParticleType>,
+	surface: Vec<Surface>,
+	filter: Vec<CollisionFilter>,
+	group: Vec<usize>,
+	alive: Vec<bool>,
+	len: usize,
+	// for constraint::rp
+	seed: u64,
+}
 
-	pub fn get_pos(&self) -> V2 {
-		self.pos
+impl Clone for Particles {
+	fn clone(&self) -> Self {
+		Self {
+			pos: self.pos.iter().map(|x| AtomicV2::new(x.load())).collect(),
+			radius: self.radius.clone(),
+			ppos: self.ppos.clone(),
+			pdt: self.pdt.clone(),
+			imass: self.imass.clone(),
+			accel: self.accel.clone(),
+			ty: self.ty.clone(),
+			surface: self.surface.clone(),
+			filter: self.filter.clone(),
+			group: self.group.clone(),
+			alive: self.alive.clone(),
+			len: self.len,
+			seed: self.seed,
+		}
 	}
+}
 
-	pub fn add_pos(&mut self, dp: V2) {
-		self.pos += dp
+impl Particles {
+	pub fn add(&mut self, p: Particle) -> usize {
+		self.pos.push(AtomicV2::new(p.pos));
+		self.radius.push(p.radius);
+		self.ppos.push(p.ppos);
+		self.pdt.push(p.pdt);
+		self.imass.push(p.imass);
+		self.accel.push(p.accel);
+		self.ty.push(p.ty);
+		self.surface.push(p.surface);
+		self.filter.push(p.filter);
+		self.group.push(p.group);
+		self.alive.push(true);
+		self.len += 1;
+		self.pos.len() - 1
 	}
 
-	pub fn offset_pos(&mut self, dp: V2) {
-		self.pos += dp;
-		self.ppos += dp;
+	pub fn get(&self, id: usize) -> Particle {
+		Particle {
+			imass: self.imass[id],
+			pos: self.pos(id),
+			radius: self.radius[id],
+			ppos: self.ppos[id],
+			pdt: self.pdt[id],
+			accel: self.accel[id],
+			ty: self.ty[id],
+			surface: self.surface[id],
+			filter: self.filter[id],
+			group: self.group[id],
+		}
 	}
 
-	pub fn reset_pos(&mut self, p: V2) {
-		self.pos = p;
-		self.ppos = p;
+	// slot is kept, so ids of other particles do not change
+	pub fn remove(&mut self, id: usize) -> bool {
+		if !self.is_alive(id) {
+			return false;
+		}
+		self.alive[id] = false;
+		self.imass[id] = 0f32;
+		self.len -= 1;
+		true
+	}
+
+	pub fn is_alive(&self, id: usize) -> bool {
+		self.alive.get(id) == Some(&true)
 	}
 
-	pub fn get_imass(&self) -> f32 {
-		self.imass
+	pub fn len(&self) -> usize {
+		self.len
 	}
 
-	pub fn update(&mut self, t: f32, max_dp: f32) {
-		if self.imass == 0f32 {
+	pub fn is_empty(&self) -> bool {
+		self.len == 0
+	}
+
+	pub fn seed(&self) -> u64 {
+		self.seed
+	}
+
+	pub fn set_seed(&mut self, seed: u64) {
+		self.seed = seed;
+	}
+
+	pub fn ids(&self) -> impl Iterator<Item = usize> + '_ {
+		(0..self.alive.len()).filter(|&id| self.alive[id])
+	}
+
+	pub fn pos(&self, id: usize) -> V2 {
+		self.pos[id].load()
+	}
+
+	// load and store are separate, so a concurrent add to the same id is
+	// lost, there is no data race but the result is wrong
+	// sound only when no two concurrent callers share a particle, which
+	// the coloring of ConstraintGroup::solve_constraints guarantees
+	pub fn add_pos(&self, id: usize, dp: V2) {
+		self.pos[id].store(self.pos[id].load() + dp);
+	}
+
+	pub fn set_pos(&mut self, id: usize, pos: V2) {
+		self.pos[id].store(pos);
+	}
+
+	pub fn radius(&self, id: usize) -> f32 {
+		self.radius[id]
+	}
+
+	pub fn ppos(&self, id: usize) -> V2 {
+		self.ppos[id]
+	}
+
+	pub fn set_ppos(&mut self, id: usize, ppos: V2) {
+		self.ppos[id] = ppos;
+	}
+
+	pub fn offset_pos(&mut self, id: usize, dp: V2) {
+		self.add_pos(id, dp);
+		self.ppos[id] += dp;
+	}
+
+	pub fn reset_pos(&mut self, id: usize, p: V2) {
+		self.set_pos(id, p);
+		self.ppos[id] = p;
+	}
+
+	// displacement in last step
+	pub fn dpos(&self, id: usize) -> V2 {
+		self.pos(id) - self.ppos[id]
+	}
+
+	pub fn pdt(&self, id: usize) -> f32 {
+		self.pdt[id]
+	}
+
+	pub fn imass(&self, id: usize) -> f32 {
+		self.imass[id]
+	}
+
+	pub fn set_imass(&mut self, id: usize, imass: f32) {
+		self.imass[id] = imass;
+	}
+
+	pub fn accel(&self, id: usize) -> V2 {
+		self.accel[id]
+	}
+
+	pub fn set_accel(&mut self, id: usize, accel: V2) {
+		self.accel[id] = accel;
+	}
+
+	pub fn ty(&self, id: usize) -> ParticleType {
+		self.ty[id]
+	}
+
+	pub fn surface(&self, id: usize) -> &Surface {
+		&self.surface[id]
+	}
+
+	pub fn filter(&self, id: usize) -> &CollisionFilter {
+		&self.filter[id]
+	}
+
+	pub fn group(&self, id: usize) -> usize {
+		self.group[id]
+	}
+
+	// pairs joined by constraints are excluded elsewhere
+	pub fn can_collide(&self, id1: usize, id2: usize) -> bool {
+		let (f1, f2) = (&self.filter[id1], &self.filter[id2]);
+		if f1.layer & f2.mask == 0 || f2.layer & f1.mask == 0 {
+			return false;
+		}
+		self.group[id1] != self.group[id2]
+			|| f1.self_collision != SelfCollision::None
+	}
+
+	pub fn update(&mut self, id: usize, t: f32, max_dp: f32) {
+		if self.imass[id] == 0f32 {
 			return;
 		}
-		let ppos = self.pos;
-		let dv = self.accel * t;
-		// NOTE: this does not work with dynamic t, should record last t and scale
-		let mut dp = self.pos - self.ppos + dv * t;
+		let pos = self.pos(id);
+		let dv = self.accel[id] * t;
+		// velocity is implicit, rescale displacement of last step
+		let pdt = self.pdt[id];
+		let k = if pdt == 0f32 { 1f32 } else { t / pdt };
+		let mut dp = (pos - self.ppos[id]) * k + dv * t;
 		if dp.magnitude() > max_dp {
 			dp = dp.normalize() * max_dp;
 		}
-		self.pos += dp;
-		self.ppos = ppos;
+		self.set_pos(id, pos + dp);
+		self.ppos[id] = pos;
+		self.pdt[id] = t;
 	}
 
-	pub fn render(&self) -> PrParticle {
+	pub fn render(&self, id: usize) -> PrParticle {
+		let pos = self.pos(id);
 		PrParticle {
-			pos: [self.pos[0], self.pos[1]],
+			pos: [pos[0], pos[1]],
+			fluid: self.ty[id] == ParticleType::Fluid,
+			model: self.group[id],
 		}
 	}
 }
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_variable_step() {
+		let mut particles = Particles::default();
+		let p = Particle::new(1.0, V2::new(0., 0.), V2::new(0., 10.));
+		let id = particles.add(p);
+		for i in 0..400 {
+			let t = if i % 2 == 0 { 0.001 } else { 0.004 };
+			particles.update(id, t, f32::INFINITY);
+		}
+		// free fall of 1s
+		assert!((particles.pos(id)[1] - 5.0).abs() < 0.02);
+		let v = particles.dpos(id)[1] / particles.pdt(id);
+		assert!((v - 10.0).abs() < 0.02);
+	}
+
+	#[test]
+	fn test_can_collide() {
+		let mut particles = Particles::default();
+		let mut add = |layer, mask, self_collision, group| {
+			let mut p = Particle::new(1.0, V2::new(0., 0.), V2::new(0., 0.));
+			p.filter = CollisionFilter {
+				layer,
+				mask,
+				self_collision,
+			};
+			p.group = group;
+			particles.add(p)
+		};
+		let a = add(1, u32::MAX, SelfCollision::None, 1);
+		let b = add(1, u32::MAX, SelfCollision::None, 1);
+		let c = add(2, 2, SelfCollision::All, 2);
+		let d = add(2, 3, SelfCollision::All, 3);
+		assert!(!particles.can_collide(a, b));
+		assert!(!particles.can_collide(a, c));
+		assert!(particles.can_collide(a, d));
+		assert!(particles.can_collide(c, d));
+	}
+}
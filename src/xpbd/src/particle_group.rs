@@ -1,30 +1,58 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 type Map<K, V> = fnv::FnvHashMap<K, V>;
+type Set<K> = fnv::FnvHashSet<K>;
 // type Map<K, V> = HashMap<K, V>;
 
-use crate::constraint::distance::DistanceConstraint;
+use crate::constraint::contact::ContactConstraint;
+use crate::constraint::density::{lattice_density, DensityConstraint};
+use crate::constraint::edge_contact::{
+	edge_side, segment_t, EdgeContactConstraint,
+};
+use crate::constraint::obstacle_contact::ObstacleContactConstraint;
 use crate::constraint::Constraint;
-use crate::particle::PRef;
+use crate::obstacle::Obstacle;
+use crate::particle::{Particle, ParticleType, Particles};
 use crate::posbox::Posbox;
 use crate::{C2, V2};
 use protocol::pr_model::PrParticle;
+use serde::{Deserialize, Serialize};
 
+#[derive(Serialize, Deserialize)]
 pub struct ParticleGroup {
-	id_alloc: usize,
-	csize: f32, // = 2 x radius
-	shp: Map<C2, Vec<PRef>>,
-	data: Map<usize, PRef>,
+	// cell size of spatial hash, particles by center
+	// particles of radius up to csize / 2 are found in adjacent cells
+	csize: f32,
+	shp: Map<C2, Vec<usize>>,
+	particles: Particles,
+	// alive particles of radius over csize / 2
+	large: Vec<usize>,
+	max_radius: f32,
+	// boundary edges for particle-edge collision, key is constraint id
+	// ordered, so collisions do not depend on insertion history
+	// edge start, edge end, third corner of its triangle(inside)
+	edges: BTreeMap<i32, [usize; 3]>,
+	// particles duplicated from the same particle do not collide
+	// id -> original id
+	twins: Map<usize, usize>,
+	// pairs of sorted ids that do not collide, e.g. remeshed neighbors
+	exclusions: Set<[usize; 2]>,
 	speed_limit_k: f32,
 	posbox: Posbox,
+	obstacles: Vec<Obstacle>,
 }
 
 impl Default for ParticleGroup {
 	fn default() -> Self {
+		let csize = 0.08;
 		Self {
-			id_alloc: 0,
-			csize: 0.08,
+			csize,
 			shp: Default::default(),
-			data: Default::default(),
+			particles: Default::default(),
+			large: Vec::new(),
+			max_radius: 0f32,
+			edges: Default::default(),
+			twins: Default::default(),
+			exclusions: Default::default(),
 			// particle cannot move more than k * csize in dt
 			speed_limit_k: 1.0,
 			posbox: Posbox {
@@ -33,6 +61,7 @@ impl Default for ParticleGroup {
 				ymin: -1e3,
 				ymax: 1e3,
 			},
+			obstacles: Vec::new(),
 		}
 	}
 }
@@ -43,67 +72,278 @@ impl ParticleGroup {
 		self
 	}
 
+	pub fn add_obstacle(&mut self, obstacle: Obstacle) {
+		self.obstacles.push(obstacle);
+	}
+
+	pub fn posbox(&self) -> &Posbox {
+		&self.posbox
+	}
+
+	pub fn obstacles(&self) -> &[Obstacle] {
+		&self.obstacles
+	}
+
 	pub fn len(&self) -> usize {
-		self.data.len()
+		self.particles.len()
+	}
+
+	pub fn particles(&self) -> &Particles {
+		&self.particles
+	}
+
+	pub fn particles_mut(&mut self) -> &mut Particles {
+		&mut self.particles
 	}
 
 	pub fn update(&mut self, dt: f32) {
-		let old_shp = std::mem::take(&mut self.shp);
-		for pref in old_shp.into_iter().flat_map(|(_, p)| p) {
-			let pos = {
-				let mut locked = pref.try_write().unwrap();
-				locked.update(dt, self.speed_limit_k * self.csize);
-				if self.posbox.apply(&mut locked.pos) {
-					locked.ppos = locked.pos;
-				}
-				locked.pos
-			};
-			let cpos = self.get_cpos(pos);
+		// rebuilt in id order, the same for a restored snapshot
+		self.shp = Default::default();
+		let ids: Vec<usize> = self.particles.ids().collect();
+		for id in ids {
+			self.particles
+				.update(id, dt, self.speed_limit_k * self.csize);
+			self.posbox.apply(&mut self.particles, id);
+			let cpos = self.get_cpos(self.particles.pos(id));
 			let e = self.shp.entry(cpos).or_insert_with(Vec::new);
-			(*e).push(pref);
+			(*e).push(id);
 		}
 	}
 
-	#[allow(clippy::needless_range_loop)]
+	// particles wider than a cell are missed by the 3x3 cell search
+	fn is_large(&self, id: usize) -> bool {
+		self.particles.radius(id) > self.csize / 2.0
+	}
+
+	fn contact(&self, id1: usize, id2: usize) -> Option<Box<dyn Constraint>> {
+		let ps = &self.particles;
+		// handled by density constraint
+		if ps.ty(id1) == ParticleType::Fluid
+			&& ps.ty(id2) == ParticleType::Fluid
+		{
+			return None;
+		}
+		if !ps.can_collide(id1, id2) || self.is_excluded(id1, id2) {
+			return None;
+		}
+		let l0 = ps.radius(id1) + ps.radius(id2);
+		let dl = (ps.pos(id1) - ps.pos(id2)).magnitude();
+		// Note: is it enough or we should make is looser?
+		// during iteration more collisions could happen
+		if dl > l0 {
+			return None;
+		}
+		Some(ContactConstraint::new(ps, id1, id2, l0).build())
+	}
+
 	fn collcon_of_2_pvecs(
 		&self,
-		pv1: &[PRef],
-		pv2: &[PRef],
+		pv1: &[usize],
+		pv2: &[usize],
 	) -> Vec<Box<dyn Constraint>> {
 		let mut result = Vec::new();
-		for p1 in pv1.iter() {
-			for p2 in pv2.iter() {
-				{
-					let pp1 = p1.read().unwrap();
-					if let Ok(ref mut pp2) = p2.read() {
-						if pp1.id >= pp2.id {
-							continue;
-						}
-						let dl = (pp1.get_pos() - pp2.get_pos()).magnitude();
-						// Note: is it enough or we should make is looser?
-						// during iteration more collisions could happen
-						if dl > self.csize {
-							continue;
-						}
+		for &id1 in pv1.iter() {
+			if self.is_large(id1) {
+				continue;
+			}
+			for &id2 in pv2.iter() {
+				if id1 >= id2 || self.is_large(id2) {
+					continue;
+				}
+				result.extend(self.contact(id1, id2));
+			}
+		}
+		result
+	}
+
+	// contacts of a large particle, searching cells in reach of any particle
+	fn large_collcons(&self, id1: usize) -> Vec<Box<dyn Constraint>> {
+		let mut result = Vec::new();
+		let pos = self.particles.pos(id1);
+		let reach = self.particles.radius(id1) + self.max_radius;
+		let r = V2::new(reach, reach);
+		let cmin = self.get_cpos(pos - r);
+		let cmax = self.get_cpos(pos + r);
+		for cx in cmin[0]..=cmax[0] {
+			for cy in cmin[1]..=cmax[1] {
+				let pvec = match self.shp.get(&C2::new(cx, cy)) {
+					Some(pvec) => pvec,
+					None => continue,
+				};
+				for &id2 in pvec.iter() {
+					// pair of large particles is added by the smaller id
+					if id2 == id1 || (id2 < id1 && self.is_large(id2)) {
+						continue;
+					}
+					result.extend(self.contact(id1, id2));
+				}
+			}
+		}
+		result
+	}
+
+	fn edge_collcons(&self, edge: &[usize; 3]) -> Vec<Box<dyn Constraint>> {
+		let ps = &self.particles;
+		let mut result = Vec::new();
+		let [id0, id1, inner] = *edge;
+		let edge_radius = (ps.radius(id0) + ps.radius(id1)) / 2.0;
+		let (pos0, pos1) = (ps.pos(id0), ps.pos(id1));
+		// 0 if the inside is unknown
+		let inner_side = if ps.is_alive(inner) {
+			edge_side(pos0, pos1, ps.pos(inner))
+		} else {
+			0.0
+		};
+		let reach = edge_radius + self.max_radius;
+		let r = V2::new(reach, reach);
+		let cmin = self.get_cpos(pos0.inf(&pos1) - r);
+		let cmax = self.get_cpos(pos0.sup(&pos1) + r);
+		for cx in cmin[0]..=cmax[0] {
+			for cy in cmin[1]..=cmax[1] {
+				let pvec = match self.shp.get(&C2::new(cx, cy)) {
+					Some(pvec) => pvec,
+					None => continue,
+				};
+				for &id in pvec.iter() {
+					if id == id0
+						|| id == id1 || !ps.can_collide(id, id0)
+						|| self.is_excluded(id, id0)
+						|| self.is_excluded(id, id1)
+					{
+						continue;
+					}
+					// like a particle pair, sum of radii
+					let thickness = edge_radius + ps.radius(id);
+					let pos = ps.pos(id);
+					let t = segment_t(pos, pos0, pos1);
+					let q = pos0 + (pos1 - pos0) * t;
+					// a particle of another model that tunnelled through
+					// is inside, it is pushed back from within reach
+					// particles of the same model are inside by nature
+					let side = if ps.group(id) == ps.group(id0) {
+						0.0
 					} else {
+						inner_side
+					};
+					let over = t > 0.0 && t < 1.0;
+					let inside = over && edge_side(pos0, pos1, pos) == side;
+					let limit = if inside { reach } else { thickness };
+					if (pos - q).magnitude() > limit {
 						continue;
 					}
+					let collcon = EdgeContactConstraint::new(
+						ps,
+						id,
+						[id0, id1],
+						side,
+						thickness,
+					)
+					.build();
+					result.push(collcon);
+				}
+			}
+		}
+		result
+	}
+
+	fn obstacle_collcons(
+		&self,
+		cell: &C2,
+		pvec: &[usize],
+	) -> Vec<Box<dyn Constraint>> {
+		let ps = &self.particles;
+		let mut result = Vec::new();
+		let cmin = V2::new(cell[0] as f32, cell[1] as f32) * self.csize;
+		let cmax = cmin + V2::new(self.csize, self.csize);
+		let r = V2::new(self.max_radius, self.max_radius);
+		for obstacle in self.obstacles.iter() {
+			let [min, max] = obstacle.aabb();
+			let (min, max) = (min - r, max + r);
+			if cmax[0] < min[0]
+				|| cmax[1] < min[1]
+				|| cmin[0] > max[0]
+				|| cmin[1] > max[1]
+			{
+				continue;
+			}
+			for &id in pvec.iter() {
+				let pos = ps.pos(id);
+				let (d, n) = match obstacle.distance(pos) {
+					Some(x) => x,
+					None => continue,
+				};
+				if d > ps.radius(id) {
+					continue;
 				}
-				let collcon = DistanceConstraint::new_with_l0(
-					p1.clone(),
-					p2.clone(),
-					self.csize,
+				let con = ObstacleContactConstraint::new(
+					ps,
+					id,
+					pos - n * d,
+					n,
+					&obstacle.surface,
 				)
-				.repulsive_only()
 				.build();
-				result.push(collcon);
+				result.push(con);
 			}
 		}
 		result
 	}
 
+	// fluid kernel radius is csize, so neighbors are in adjacent cells
+	// rest density is of a lattice of the particle's mass, spaced by its
+	// radius(see FluidModelBuilder)
+	fn density_constraints(
+		&self,
+		cell: &C2,
+		pvec: &[usize],
+	) -> Vec<Box<dyn Constraint>> {
+		let ps = &self.particles;
+		let mut result = Vec::new();
+		for &id in pvec.iter() {
+			if ps.ty(id) != ParticleType::Fluid || ps.imass(id) == 0.0 {
+				continue;
+			}
+			let pos = ps.pos(id);
+			let mut neighbors = Vec::new();
+			for dx in -1..=1 {
+				for dy in -1..=1 {
+					let cell2 = cell + C2::new(dx, dy);
+					let pvec2 = match self.shp.get(&cell2) {
+						Some(pvec2) => pvec2,
+						None => continue,
+					};
+					for &id2 in pvec2.iter() {
+						if id2 == id || ps.ty(id2) != ParticleType::Fluid {
+							continue;
+						}
+						if (ps.pos(id2) - pos).magnitude() < self.csize {
+							neighbors.push(id2);
+						}
+					}
+				}
+			}
+			if neighbors.is_empty() {
+				continue;
+			}
+			let rho0 =
+				lattice_density(self.csize, ps.radius(id)) / ps.imass(id);
+			let con =
+				DensityConstraint::new(id, neighbors, self.csize, rho0).build();
+			result.push(con);
+		}
+		result
+	}
+
 	pub fn collision_constraints(&mut self) -> Vec<Box<dyn Constraint>> {
 		use rayon::prelude::*;
+		let edge_collcons = self
+			.edges
+			.par_iter()
+			.flat_map(|(_, edge)| self.edge_collcons(edge).into_par_iter());
+		let large_collcons = self
+			.large
+			.par_iter()
+			.flat_map(|&id| self.large_collcons(id).into_par_iter());
 		self.shp
 			.par_iter()
 			.flat_map(|(cell, pvec)| {
@@ -111,6 +351,8 @@ impl ParticleGroup {
 				if pvec.is_empty() {
 					eprintln!("WARN: empty cell(bug)");
 				}
+				result.extend(self.density_constraints(cell, pvec));
+				result.extend(self.obstacle_collcons(cell, pvec));
 				for dcell in vec![
 					C2::new(-1, -1),
 					C2::new(-1, 0),
@@ -132,13 +374,15 @@ impl ParticleGroup {
 				}
 				result.into_par_iter()
 			})
+			.chain(edge_collcons)
+			.chain(large_collcons)
 			.collect()
 	}
 
 	pub fn pr_particles(&self) -> HashMap<usize, PrParticle> {
 		let mut result = HashMap::default();
-		for (&id, p) in self.data.iter() {
-			let prp = p.try_read().unwrap().render();
+		for id in self.particles.ids() {
+			let prp = self.particles.render(id);
 			assert!(result.insert(id, prp).is_none());
 		}
 		result
@@ -151,21 +395,112 @@ impl ParticleGroup {
 		)
 	}
 
-	pub fn add_pref(&mut self, p: PRef) -> usize {
-		let pos = {
-			let mut p = p.try_write().unwrap();
-			p.id = self.id_alloc;
-			p.get_pos()
-		};
-		assert!(self.data.insert(self.id_alloc, p.clone()).is_none());
-		let cpos = self.get_cpos(pos);
+	pub fn add_particle(&mut self, p: Particle) -> usize {
+		let cpos = self.get_cpos(p.pos);
+		let radius = p.radius;
+		let id = self.particles.add(p);
+		self.max_radius = self.max_radius.max(radius);
+		if self.is_large(id) {
+			self.large.push(id);
+		}
 		let e = self.shp.entry(cpos).or_insert_with(Vec::new);
-		(*e).push(p);
-		self.id_alloc += 1;
-		self.id_alloc - 1
+		(*e).push(id);
+		id
+	}
+
+	fn twin_root(&self, id: usize) -> usize {
+		*self.twins.get(&id).unwrap_or(&id)
 	}
 
-	pub fn get_pref(&self, id: usize) -> Option<PRef> {
-		self.data.get(&id).cloned()
+	fn is_excluded(&self, id1: usize, id2: usize) -> bool {
+		if self.twin_root(id1) == self.twin_root(id2) {
+			return true;
+		}
+		let mut ids = [id1, id2];
+		ids.sort_unstable();
+		self.exclusions.contains(&ids)
+	}
+
+	pub fn add_exclusion(&mut self, id1: usize, id2: usize) {
+		if id1 == id2 {
+			return;
+		}
+		let mut ids = [id1, id2];
+		ids.sort_unstable();
+		self.exclusions.insert(ids);
+	}
+
+	pub fn add_twin(&mut self, id: usize, new_id: usize) {
+		let root = self.twin_root(id);
+		self.twins.insert(new_id, root);
+	}
+
+	// replace if exists
+	// ps: edge start, edge end, third corner of its triangle
+	pub fn add_edge(&mut self, cid: i32, ps: [usize; 3]) {
+		self.edges.insert(cid, ps);
+	}
+
+	pub fn remove_edges(&mut self, cids: &[i32]) {
+		for cid in cids.iter() {
+			self.edges.remove(cid);
+		}
+	}
+
+	pub fn remove_particle(&mut self, id: usize) -> bool {
+		if !self.particles.is_alive(id) {
+			return false;
+		}
+		self.remove_particles(&[id].into_iter().collect());
+		true
+	}
+
+	// in one pass over the spatial hash
+	pub fn remove_particles(&mut self, ids: &HashSet<usize>) {
+		let ids: HashSet<usize> = ids
+			.iter()
+			.copied()
+			.filter(|&id| self.particles.remove(id))
+			.collect();
+		if ids.is_empty() {
+			return;
+		}
+		self.shp.retain(|_, pvec| {
+			pvec.retain(|x| !ids.contains(x));
+			!pvec.is_empty()
+		});
+		self.large.retain(|x| !ids.contains(x));
+		self.twins.retain(|k, _| !ids.contains(k));
+		self.exclusions
+			.retain(|x| !ids.contains(&x[0]) && !ids.contains(&x[1]));
+	}
+
+	pub fn contains(&self, id: usize) -> bool {
+		self.particles.is_alive(id)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	// a particle wider than a cell touches one several cells away
+	#[test]
+	fn test_large_contact() {
+		let mut pg = ParticleGroup::default();
+		let mut add = |x: f32, radius: f32| {
+			let mut p = Particle::new(1.0, V2::new(x, 0.), V2::zeros());
+			p.radius = radius;
+			pg.add_particle(p)
+		};
+		let large = add(0.0, 0.3);
+		let small = add(0.3, 0.04);
+		// out of reach
+		add(0.5, 0.04);
+		let collcons = pg.collision_constraints();
+		assert_eq!(collcons.len(), 1);
+		let mut ids = collcons[0].render(0).particles;
+		ids.sort_unstable();
+		assert_eq!(ids, vec![large, small]);
 	}
 }
@@ -1,22 +1,31 @@
 use crate::constraint::constraint_template::ConstraintTemplate;
-use crate::particle::ParticleTemplate;
+use crate::hierarchy::HierarchyTemplate;
+use crate::particle::{CollisionFilter, ParticleTemplate};
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Default)]
+// saved with bincode by material::model_file, a layout change here or
+// in the templates it holds must increase VERSION there
+#[derive(Clone, Default, Serialize, Deserialize)]
 pub struct PhysicalModel {
 	pub particles: Vec<ParticleTemplate>,
 	pub constraints: Vec<ConstraintTemplate>,
 	// for each vec: first object depends on existence of all others
 	// usize for constraints idx
 	pub dependencies: Vec<[usize; 2]>,
+	// coarse levels for hpbd, from fine to coarse
+	pub levels: Vec<HierarchyTemplate>,
+	// shared by all particles of the model
+	pub collision: CollisionFilter,
 }
 
 impl std::fmt::Debug for PhysicalModel {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 		writeln!(
 			f,
-			"PhysicalModel with plen: {}, clen: {}",
+			"PhysicalModel with plen: {}, clen: {}, levels: {}",
 			self.particles.len(),
 			self.constraints.len(),
+			self.levels.len(),
 		)
 	}
 }
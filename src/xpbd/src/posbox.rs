@@ -1,5 +1,7 @@
-use crate::V2;
+use crate::particle::Particles;
+use serde::{Deserialize, Serialize};
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Posbox {
 	pub xmin: f32,
 	pub xmax: f32,
@@ -8,26 +10,79 @@ pub struct Posbox {
 }
 
 impl Posbox {
-	pub fn apply(&self, pos: &mut V2) -> bool {
-		let xmin = self.xmin;
-		let xmax = self.xmax;
-		let ymin = self.ymin;
-		let ymax = self.ymax;
-		let mut flag = false;
-		if pos[0] < xmin {
-			pos[0] = xmin;
-			flag = true;
-		} else if pos[0] > xmax {
-			pos[0] = xmax;
-			flag = true;
-		};
-		if pos[1] < ymin {
-			pos[1] = ymin;
-			flag = true;
-		} else if pos[1] > ymax {
-			pos[1] = ymax;
-			flag = true;
+	// move particle back into the box by its radius,
+	// bounce by restitution and slow down tangent motion by friction
+	pub fn apply(&self, particles: &mut Particles, id: usize) {
+		let surface = *particles.surface(id);
+		let r = particles.radius(id);
+		for axis in 0..2 {
+			let (min, max) = if axis == 0 {
+				(self.xmin + r, self.xmax - r)
+			} else {
+				(self.ymin + r, self.ymax - r)
+			};
+			let mut pos = particles.pos(id);
+			let wall = if pos[axis] < min {
+				min
+			} else if pos[axis] > max {
+				max
+			} else {
+				continue;
+			};
+			let dp = particles.dpos(id);
+			let vn = dp[axis];
+			let vt = dp[1 - axis];
+			pos[axis] = wall;
+			let mut ppos = particles.ppos(id);
+			ppos[axis] = wall + surface.restitution * vn;
+			// coulomb friction, normal impulse is proportional to vn
+			let friction = vn.abs() * surface.kinetic_friction;
+			let vt = if vt.abs() <= vn.abs() * surface.static_friction {
+				0.0
+			} else {
+				vt - friction.min(vt.abs()) * vt.signum()
+			};
+			ppos[1 - axis] = pos[1 - axis] - vt;
+			particles.set_pos(id, pos);
+			particles.set_ppos(id, ppos);
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::particle::Particle;
+	use crate::V2;
+
+	// x displacement per step after sliding on the floor of a box
+	// tilted so that tan of the slope is k
+	fn slide(k: f32, v0: f32) -> f32 {
+		let posbox = Posbox {
+			xmin: -100.0,
+			xmax: 100.0,
+			ymin: -1.0,
+			ymax: 0.0,
 		};
-		flag
+		let dt = 1.0 / 60.0;
+		let mut particles = Particles::default();
+		let g = V2::new(k, 1.0) * 9.8;
+		let mut p = Particle::new(1.0, V2::new(0., -0.04), g);
+		p.ppos = p.pos - V2::new(v0 * dt, 0.0);
+		let id = particles.add(p);
+		for _ in 0..300 {
+			particles.update(id, dt, f32::INFINITY);
+			posbox.apply(&mut particles, id);
+		}
+		particles.dpos(id)[0]
+	}
+
+	#[test]
+	fn test_friction() {
+		// static 0.4, kinetic 0.3
+		assert_eq!(slide(0.2, 1.0), 0.0);
+		assert_eq!(slide(0.35, 0.0), 0.0);
+		assert!(slide(0.35, 1.0) > 1.0 / 60.0);
+		assert!(slide(0.5, 0.0) > 0.0);
 	}
 }
@@ -1,26 +1,37 @@
+use std::collections::{HashMap, HashSet};
 use std::sync::mpsc::{Receiver, Sender};
+use std::time::Instant;
 
 use crate::constraint::constraint_template::ConstraintTemplate;
-use crate::constraint::distance::DistanceConstraint;
 use crate::constraint::leash::LeashConstraint;
-use crate::constraint::volume::VolumeConstraint;
 use crate::constraint_group::ConstraintGroup;
 use crate::controller_message::ControllerMessage;
-use crate::particle::Particle;
+use crate::force_field::ForceField;
+use crate::history::History;
+use crate::model_handle::ModelHandle;
+use crate::obstacle::Obstacle;
+use crate::particle::{Particle, ParticleType, SelfCollision};
 use crate::particle_group::ParticleGroup;
 use crate::physical_model::PhysicalModel;
 use crate::posbox::Posbox;
+use crate::recorder::{Recorder, Replay};
+use crate::remesh::{Remesh, Split};
+use crate::topology::Topology;
 use crate::V2;
-use protocol::pr_model::PrModel;
+use protocol::pr_model::{PrFace, PrModel};
 use protocol::user_event::UpdateInfo;
 use protocol::user_event::UserEvent;
+use serde::{Deserialize, Serialize};
 use stpw::Timer;
 
+#[derive(Serialize, Deserialize)]
 pub struct PWorld {
 	pub dt: f32,
 	pub ppr: usize,
 	pub time_scale: f32,
 	iteration: usize,
+	// run_thread follows elapsed real time instead of sleeping
+	realtime: bool,
 
 	// -1: always play
 	// 0: pause
@@ -29,8 +40,31 @@ pub struct PWorld {
 
 	pg: ParticleGroup,
 	cg: ConstraintGroup,
+	// particle group of last added model, 0 for none
+	model_group: usize,
+	// simulated time, for time varying fields
+	time: f32,
+	// number of frames(run or run_for) done
+	frame: usize,
+	gravity: V2,
+	force_fields: Vec<ForceField>,
 
 	print_perf: bool,
+	hpbd: bool,
+	// split mesh when edge breaks
+	fracture: bool,
+	topology: Topology,
+	// refine strained faces and coarsen them at rest
+	remesh: Option<Remesh>,
+	// snapshots for rewinding, not part of a snapshot
+	#[serde(skip)]
+	history: Option<History>,
+	// log of applied controller messages
+	#[serde(skip)]
+	recorder: Option<Recorder>,
+	// recorded messages fed to run_thread
+	#[serde(skip)]
+	replay: Option<Replay>,
 }
 
 impl Default for PWorld {
@@ -40,12 +74,25 @@ impl Default for PWorld {
 			ppr: 5,
 			time_scale: 1.0,
 			iteration: 6,
+			realtime: false,
 			forward_frames: -1,
 
 			pg: Default::default(),
 			cg: Default::default(),
+			model_group: 0,
+			time: 0f32,
+			frame: 0,
+			gravity: V2::new(0., 9.8),
+			force_fields: Vec::new(),
 
 			print_perf: false,
+			hpbd: false,
+			fracture: false,
+			topology: Default::default(),
+			remesh: None,
+			history: None,
+			recorder: None,
+			replay: None,
 		}
 	}
 }
@@ -56,6 +103,41 @@ impl PWorld {
 		self
 	}
 
+	pub fn with_obstacle(mut self, obstacle: Obstacle) -> Self {
+		self.pg.add_obstacle(obstacle);
+		self
+	}
+
+	pub fn posbox(&self) -> &Posbox {
+		self.pg.posbox()
+	}
+
+	pub fn obstacles(&self) -> &[Obstacle] {
+		self.pg.obstacles()
+	}
+
+	pub fn with_gravity(mut self, gravity: V2) -> Self {
+		self.gravity = gravity;
+		self
+	}
+
+	pub fn set_gravity(&mut self, gravity: V2) {
+		self.gravity = gravity;
+	}
+
+	pub fn with_force_field(mut self, field: ForceField) -> Self {
+		self.force_fields.push(field);
+		self
+	}
+
+	pub fn add_force_field(&mut self, field: ForceField) {
+		self.force_fields.push(field);
+	}
+
+	pub fn clear_force_fields(&mut self) {
+		self.force_fields.clear();
+	}
+
 	pub fn with_time_scale(mut self, time_scale: f32) -> Self {
 		self.time_scale = time_scale;
 		self
@@ -66,6 +148,11 @@ impl PWorld {
 		self
 	}
 
+	pub fn with_realtime(mut self) -> Self {
+		self.realtime = true;
+		self
+	}
+
 	pub fn with_paused(mut self) -> Self {
 		self.forward_frames = 1; // provide first frame
 		self
@@ -77,6 +164,61 @@ impl PWorld {
 		self
 	}
 
+	// solve coarse levels of models before each iteration
+	pub fn with_hpbd(mut self) -> Self {
+		self.hpbd = true;
+		self
+	}
+
+	// faces are kept on break, and particles on crack are duplicated
+	// must be set before adding models
+	pub fn with_fracture(mut self) -> Self {
+		self.fracture = true;
+		self
+	}
+
+	// must be set before adding models
+	pub fn with_remesh(mut self, remesh: Remesh) -> Self {
+		self.remesh = Some(remesh);
+		self
+	}
+
+	// keep capacity snapshots, taken every interval frames
+	pub fn with_history(mut self, interval: usize, capacity: usize) -> Self {
+		self.history = Some(History::new(interval, capacity));
+		self
+	}
+
+	// messages applied by run_thread are logged
+	pub fn with_recorder(mut self, recorder: Recorder) -> Self {
+		if self.realtime {
+			eprintln!("WARN: realtime run is not replayed exactly");
+		}
+		self.recorder = Some(recorder);
+		self
+	}
+
+	// world at the start of the recording, run_thread applies the
+	// recorded messages at their frames, realtime is disabled
+	pub fn from_replay(replay: Replay) -> bincode::Result<Self> {
+		let mut pworld = Self::from_snapshot(&replay.snapshot)?;
+		pworld.realtime = false;
+		pworld.replay = Some(replay);
+		Ok(pworld)
+	}
+
+	// seed of the offsets separating coinciding particles
+	pub fn with_seed(mut self, seed: u64) -> Self {
+		self.pg.particles_mut().set_seed(seed);
+		self
+	}
+
+	// solver iterations per step
+	pub fn with_iteration(mut self, iteration: usize) -> Self {
+		self.iteration = iteration;
+		self
+	}
+
 	pub fn with_ppr(mut self, ppr: usize) -> Self {
 		self.ppr = ppr;
 		self
@@ -86,51 +228,840 @@ impl PWorld {
 		&mut self,
 		physical_model: PhysicalModel,
 		offset: V2,
-	) -> Vec<i32> {
+	) -> ModelHandle {
 		let mut cids = Vec::new();
 		eprintln!("INFO: add model: {:?}", physical_model);
-		let mut id_map = vec![];
-		for p in physical_model.particles.into_iter() {
-			let p =
-				Particle::new_ref(0, p.imass, p.pos + offset, V2::new(0., 9.8));
-			self.pg.add_pref(p.clone());
-			id_map.push(p);
+		self.model_group += 1;
+		let filter = physical_model.collision;
+		let mut pids = vec![];
+		for pt in physical_model.particles.into_iter() {
+			let mut p = Particle::new(pt.imass, pt.pos + offset, self.gravity);
+			p.radius = pt.radius;
+			p.ty = pt.ty;
+			p.surface = pt.surface;
+			p.filter = filter;
+			p.group = self.model_group;
+			pids.push(self.pg.add_particle(p));
+		}
+		if filter.self_collision == SelfCollision::Unconnected {
+			// a constraint over all particles would exclude all pairs
+			for c in physical_model.constraints.iter().filter(|c| c.is_local())
+			{
+				let ps = c.particles();
+				for (i, &p1) in ps.iter().enumerate() {
+					for &p2 in ps[i + 1..].iter() {
+						self.pg.add_exclusion(pids[p1], pids[p2]);
+					}
+				}
+			}
 		}
+		// edges owned by exactly one triangle are on the outline
+		// sorted edge -> third corners of its triangles
+		let mut corners: HashMap<[usize; 2], Vec<usize>> = HashMap::new();
+		for c in physical_model.constraints.iter() {
+			if let ConstraintTemplate::Volume(ct) = c {
+				for i in 0..3 {
+					let mut ids = [ct.ps[i], ct.ps[(i + 1) % 3]];
+					ids.sort_unstable();
+					corners.entry(ids).or_default().push(ct.ps[(i + 2) % 3]);
+				}
+			}
+		}
+		let mut outline = Vec::new();
+		let mut faces = Vec::new();
 		for c in physical_model.constraints.into_iter() {
-			use ConstraintTemplate::*;
-			let con = match c {
-				Distance(ct) => {
-					let p1 = id_map[ct.ps[0]].clone();
-					let p2 = id_map[ct.ps[1]].clone();
-					DistanceConstraint::new_with_l0(p1, p2, ct.l0)
-						.with_compliance(ct.compliance)
-						.with_ty(ct.ty)
-						.build()
-				}
-				Volume(ct) => {
-					let ps = (0..3).map(|i| id_map[ct.ps[i]].clone()).collect();
-					VolumeConstraint::new(ps)
-						.with_compliance(ct.compliance)
-						.build()
+			let mut edge = None;
+			let mut face = None;
+			match &c {
+				ConstraintTemplate::Distance(ct) => {
+					let mut ids = [ct.ps[0], ct.ps[1]];
+					ids.sort_unstable();
+					if let Some(&[inner]) = corners.get(&ids).map(|x| &x[..]) {
+						outline.push((cids.len(), [ids[0], ids[1], inner]));
+					}
+					edge = Some([pids[ids[0]], pids[ids[1]]]);
 				}
-			};
+				ConstraintTemplate::Volume(ct) => {
+					face =
+						Some([pids[ct.ps[0]], pids[ct.ps[1]], pids[ct.ps[2]]]);
+				}
+				_ => {}
+			}
+			let con = c.instantiate(&pids, self.pg.particles());
 			let cid = self.cg.add_constraint(con);
 			cids.push(cid);
+			if face.is_some() {
+				faces.push(cid);
+			}
+			if self.fracture || self.remesh.is_some() {
+				if let Some(ps) = edge {
+					self.topology.add_edge(cid, ps);
+				}
+				if let Some(ps) = face {
+					self.topology.add_face(cid, ps);
+				}
+			}
 		}
+		for (idx, ids) in outline.into_iter() {
+			self.pg.add_edge(cids[idx], ids.map(|id| pids[id]));
+		}
+		self.cg.add_levels(
+			physical_model.levels,
+			&pids,
+			&cids,
+			self.pg.particles(),
+		);
 		for v in physical_model.dependencies.iter() {
 			let key = cids[v[0]];
-			let value = v.iter().skip(1).map(|&x| cids[x]).collect();
+			// faces survive the break of edges in fracture mode
+			let value = v
+				.iter()
+				.skip(1)
+				.map(|&x| cids[x])
+				.filter(|&cid| !self.fracture || !self.topology.has_face(cid))
+				.collect();
 			self.cg.add_dependency(key, value);
 		}
-		cids
+		ModelHandle {
+			group: self.model_group,
+			particles: pids,
+			constraints: cids,
+			faces,
+		}
+	}
+
+	fn split_vertex(&mut self, v: usize) {
+		if !self.pg.contains(v) {
+			return;
+		}
+		// first component keeps the original particle
+		let components = self.topology.fan_components(v);
+		if components.len() < 2 {
+			self.update_outline(v);
+			return;
+		}
+		let removed = self.cg.detach_particle(v);
+		self.forget_constraints(&removed);
+		// mass is shared by face count
+		let total: usize = components.iter().map(|x| x.len()).sum();
+		let imass = self.pg.particles().imass(v);
+		let share = |faces: &[i32]| imass * total as f32 / faces.len() as f32;
+		self.pg.particles_mut().set_imass(v, share(&components[0]));
+		for faces in components.into_iter().skip(1) {
+			let mut p = self.pg.particles().get(v);
+			p.imass = share(&faces);
+			let nv = self.pg.add_particle(p);
+			self.pg.add_twin(v, nv);
+			for &cid in faces.iter() {
+				self.cg.replace_particle(cid, v, nv);
+			}
+			for cid in self.topology.split_vertex(v, nv, &faces) {
+				self.cg.replace_particle(cid, v, nv);
+			}
+			self.update_outline(nv);
+		}
+		self.update_outline(v);
+	}
+
+	fn update_outline(&mut self, v: usize) {
+		for (cid, ps) in self.topology.fan_edges(v) {
+			let faces = self.topology.edge_faces(ps);
+			if faces.len() != 1 {
+				continue;
+			}
+			let inner = self
+				.topology
+				.face(faces[0])
+				.and_then(|x| x.into_iter().find(|id| !ps.contains(id)));
+			if let Some(inner) = inner {
+				self.pg.add_edge(cid, [ps[0], ps[1], inner]);
+			}
+		}
+	}
+
+	fn fracture(&mut self, removed: &[i32]) {
+		for &cid in removed.iter() {
+			if let Some(ps) = self.topology.remove_edge(cid) {
+				self.split_vertex(ps[0]);
+				self.split_vertex(ps[1]);
+			} else {
+				self.topology.remove_face(cid);
+			}
+		}
+	}
+
+	fn distance(&self, ps: [usize; 2]) -> Option<f32> {
+		if !self.pg.contains(ps[0]) || !self.pg.contains(ps[1]) {
+			return None;
+		}
+		let particles = self.pg.particles();
+		Some((particles.pos(ps[0]) - particles.pos(ps[1])).magnitude())
+	}
+
+	fn edge_rest(&self, ps: [usize; 2]) -> Option<f32> {
+		let cid = self.topology.edge_cid(ps)?;
+		self.cg.get(cid)?.rest()
+	}
+
+	fn strain(&self, cid: i32, ps: [usize; 2]) -> Option<f32> {
+		let l0 = self.cg.get(cid)?.rest()?;
+		let l = self.distance(ps)?;
+		Some((l / l0 - 1.0).abs())
+	}
+
+	// insert particle at midpoint, split the edge and faces around it
+	fn split_edge(&mut self, cid: i32, ps: [usize; 2]) {
+		let [a, b] = ps;
+		let faces = self.topology.edge_faces(ps);
+		if faces.iter().any(|&fcid| self.cg.get(fcid).is_none()) {
+			return;
+		}
+		if !self.pg.contains(a) || !self.pg.contains(b) {
+			return;
+		}
+		// before the edge is removed
+		let strain = self.strain(cid, ps).unwrap_or(0.0);
+		let edge = match self.cg.remove_constraint(cid) {
+			Some(edge) => edge,
+			None => return,
+		};
+		let l0 = edge.rest().unwrap();
+		// a quarter of the mass of each movable end moves to the midpoint,
+		// so the body keeps its mass, fixed ends give nothing
+		let moved = ps.map(|x| match self.pg.particles().imass(x) {
+			0.0 => 0.0,
+			imass => 0.25 / imass,
+		});
+		let np = {
+			let pa = self.pg.particles().get(a);
+			let pb = self.pg.particles().get(b);
+			let mut p = pa.clone();
+			p.pos = (pa.pos + pb.pos) / 2.0;
+			p.ppos = (pa.ppos + pb.ppos) / 2.0;
+			let mass = moved[0] + moved[1];
+			p.imass = if mass == 0.0 { 0.0 } else { 1.0 / mass };
+			p
+		};
+		let particles = self.pg.particles_mut();
+		for (&x, &dm) in ps.iter().zip(moved.iter()) {
+			if dm != 0.0 {
+				particles.set_imass(x, 1.0 / (1.0 / particles.imass(x) - dm));
+			}
+		}
+		let m = self.pg.add_particle(np);
+		self.topology.remove_edge(cid);
+		self.pg.remove_edges(&[cid]);
+		let mut new_edges = Vec::new();
+		for (end, other) in [(a, b), (b, a)] {
+			let mut con = edge.clone();
+			con.replace_particle(other, m);
+			con.scale_rest(0.5);
+			let ncid = self.cg.add_constraint(con);
+			self.topology.add_edge(ncid, [end, m]);
+			new_edges.push((ncid, self.topology.edge(ncid).unwrap()));
+		}
+		self.cg.replace_span(cid, &[new_edges[0].0, new_edges[1].0]);
+		let mut old_faces = Vec::new();
+		let mut new_faces = Vec::new();
+		let mut derived = Vec::new();
+		for fcid in faces.into_iter() {
+			let fps = self.topology.face(fcid).unwrap();
+			let face = self.cg.remove_constraint(fcid).unwrap();
+			self.topology.remove_face(fcid);
+			let c = *fps.iter().find(|&&x| x != a && x != b).unwrap();
+			// median of the rest triangle
+			let spoke_l0 =
+				match (self.edge_rest([a, c]), self.edge_rest([b, c])) {
+					(Some(la), Some(lb)) => {
+						((2.0 * la * la + 2.0 * lb * lb - l0 * l0) / 4.0).sqrt()
+					}
+					_ => f32::NAN,
+				};
+			let spoke_l0 = if spoke_l0.is_normal() {
+				spoke_l0
+			} else {
+				self.distance([m, c]).unwrap()
+			};
+			let mut spoke = edge.clone();
+			spoke.replace_particle(a, m);
+			spoke.replace_particle(b, c);
+			spoke.scale_rest(spoke_l0 / l0);
+			let scid = self.cg.add_constraint(spoke);
+			self.topology.add_edge(scid, [m, c]);
+			new_edges.push((scid, self.topology.edge(scid).unwrap()));
+
+			let (root, bary) = self.remesh.as_ref().unwrap().root_of(fcid);
+			let ia = fps.iter().position(|&x| x == a).unwrap();
+			let ib = fps.iter().position(|&x| x == b).unwrap();
+			for x in [a, b] {
+				let mut con = face.clone();
+				con.replace_particle(x, m);
+				con.scale_rest(0.5);
+				let nfps = fps.map(|v| if v == x { m } else { v });
+				// corners relative to parent face, then to root face
+				let mut nbary = [[0.0; 3]; 3];
+				for i in 0..3 {
+					let mut local = [0.0; 3];
+					if fps[i] == x {
+						local[ia] = 0.5;
+						local[ib] = 0.5;
+					} else {
+						local[i] = 1.0;
+					}
+					for j in 0..3 {
+						for k in 0..3 {
+							nbary[i][k] += local[j] * bary[j][k];
+						}
+					}
+				}
+				let ncid = self.cg.add_constraint(con);
+				self.topology.add_face(ncid, nfps);
+				derived.push((ncid, (root, nbary)));
+				new_faces.push((ncid, nfps));
+			}
+			old_faces.push((fcid, face, fps));
+		}
+		if !self.fracture {
+			for (fcid, fps) in new_faces.iter() {
+				for i in 0..3 {
+					let ps = [fps[i], fps[(i + 1) % 3]];
+					if let Some(ecid) = self.topology.edge_cid(ps) {
+						self.cg.add_dependency(ecid, vec![*fcid]);
+					}
+				}
+			}
+		}
+		// new particle is closer than csize to particles of nearby rings
+		let mut ring = vec![m];
+		for _ in 0..3 {
+			for v in std::mem::take(&mut ring) {
+				for w in self.topology.ring(v) {
+					if !ring.contains(&w) {
+						ring.push(w);
+					}
+				}
+			}
+		}
+		for w in ring.into_iter() {
+			self.pg.add_exclusion(m, w);
+		}
+		self.update_outline(m);
+		let remesh = self.remesh.as_mut().unwrap();
+		remesh.faces.extend(derived);
+		let threshold = strain + remesh.refine_strain;
+		for &(ncid, _) in new_edges.iter() {
+			remesh.thresholds.insert(ncid, threshold);
+		}
+		remesh.splits.push(Split {
+			pid: m,
+			edge: (cid, edge, ps),
+			faces: old_faces,
+			new_edges,
+			new_faces,
+			moved,
+		});
+	}
+
+	fn refine(&mut self) {
+		let remesh = match self.remesh.as_ref() {
+			Some(remesh) => remesh,
+			None => return,
+		};
+		let min_length = remesh.min_length;
+		// faces with strained edge are refined by bisecting longest edge
+		let mut strained = Vec::new();
+		for (cid, ps) in self.topology.edges() {
+			let threshold = remesh
+				.thresholds
+				.get(&cid)
+				.copied()
+				.unwrap_or(remesh.refine_strain);
+			if self.strain(cid, ps).unwrap_or(0.0) <= threshold {
+				continue;
+			}
+			for fcid in self.topology.edge_faces(ps) {
+				let ps = match self.longest_edge(fcid) {
+					Some(ps) => ps,
+					None => continue,
+				};
+				if strained.contains(&ps) {
+					continue;
+				}
+				match self.edge_rest(ps) {
+					Some(l0) if l0 / 2.0 >= min_length => {}
+					_ => continue,
+				}
+				// keep triangles in good shape
+				let longest = self
+					.topology
+					.edge_faces(ps)
+					.into_iter()
+					.all(|x| self.longest_edge(x) == Some(ps));
+				if longest {
+					strained.push(ps);
+				}
+			}
+		}
+		for ps in strained.into_iter() {
+			if let Some(cid) = self.topology.edge_cid(ps) {
+				self.split_edge(cid, ps);
+			}
+		}
+	}
+
+	// sorted particle ids of the edge with largest rest length
+	fn longest_edge(&self, fcid: i32) -> Option<[usize; 2]> {
+		let fps = self.topology.face(fcid)?;
+		let mut result = None;
+		let mut max_l0 = 0.0;
+		for i in 0..3 {
+			let mut ps = [fps[i], fps[(i + 1) % 3]];
+			ps.sort_unstable();
+			if let Some(l0) = self.edge_rest(ps) {
+				if l0 > max_l0 {
+					max_l0 = l0;
+					result = Some(ps);
+				}
+			}
+		}
+		result
+	}
+
+	fn can_merge(&self, split: &Split, max_strain: f32, max_dp: f32) -> bool {
+		for &(cid, ps) in split.new_edges.iter() {
+			if self.topology.edge(cid) != Some(ps) {
+				return false;
+			}
+			match self.strain(cid, ps) {
+				Some(strain) if strain < max_strain => {}
+				_ => return false,
+			}
+		}
+		for &(cid, ps) in split.new_faces.iter() {
+			if self.topology.face(cid) != Some(ps) || self.cg.get(cid).is_none()
+			{
+				return false;
+			}
+		}
+		for v in self.topology.ring(split.pid) {
+			if !self.pg.contains(v) {
+				return false;
+			}
+			if self.pg.particles().dpos(v).magnitude() > max_dp {
+				return false;
+			}
+		}
+		true
+	}
+
+	fn merge(&mut self, split: Split) {
+		let remesh = self.remesh.as_mut().unwrap();
+		for (cid, _) in split.new_faces.iter() {
+			self.cg.remove_constraint(*cid);
+			self.topology.remove_face(*cid);
+			remesh.faces.remove(cid);
+		}
+		let (cid, edge, ps) = split.edge;
+		let cids: Vec<i32> = split.new_edges.iter().map(|x| x.0).collect();
+		for &ncid in cids.iter() {
+			self.cg.remove_constraint(ncid);
+			self.cg.replace_span(ncid, &[cid]);
+			self.topology.remove_edge(ncid);
+		}
+		self.pg.remove_edges(&cids);
+		// mass of the midpoint goes back to the ends as it was taken
+		let particles = self.pg.particles_mut();
+		let imass = particles.imass(split.pid);
+		let total = split.moved[0] + split.moved[1];
+		if imass != 0.0 && total != 0.0 {
+			for (&x, &dm) in ps.iter().zip(split.moved.iter()) {
+				if dm == 0.0
+					|| !particles.is_alive(x)
+					|| particles.imass(x) == 0.0
+				{
+					continue;
+				}
+				let mass = 1.0 / particles.imass(x) + dm / total / imass;
+				particles.set_imass(x, 1.0 / mass);
+			}
+		}
+		self.cg.uncontrol_particle(split.pid);
+		self.pg.remove_particle(split.pid);
+		self.cg.insert_constraint(cid, edge);
+		self.topology.add_edge(cid, ps);
+		for (fcid, face, fps) in split.faces.into_iter() {
+			self.cg.insert_constraint(fcid, face);
+			self.topology.add_face(fcid, fps);
+		}
+		self.update_outline(ps[0]);
+		self.update_outline(ps[1]);
+	}
+
+	fn coarsen(&mut self, dt: f32) {
+		let (mut splits, max_strain, max_dp) = match self.remesh.as_mut() {
+			Some(remesh) => (
+				std::mem::take(&mut remesh.splits),
+				remesh.coarsen_strain,
+				remesh.rest_speed * dt,
+			),
+			None => return,
+		};
+		// latest first, so nested splits are merged from inside
+		let mut idx = splits.len();
+		let mut merged = false;
+		while idx > 0 {
+			idx -= 1;
+			if self.can_merge(&splits[idx], max_strain, max_dp) {
+				let split = splits.remove(idx);
+				self.merge(split);
+				merged = true;
+			}
+		}
+		let remesh = self.remesh.as_mut().unwrap();
+		remesh.splits = splits;
+		if merged {
+			remesh.prune(|id| self.cg.get(id).is_some());
+		}
 	}
 
 	pub fn pr_model(&self) -> PrModel {
 		let ps = self.pg.pr_particles();
 		let cs = self.cg.pr_constraints();
+		let faces = match self.remesh.as_ref() {
+			Some(remesh) => remesh
+				.faces
+				.iter()
+				// faces split again are kept for coarsening
+				.filter(|(&id, _)| self.cg.get(id).is_some())
+				.map(|(&id, &(root, bary))| PrFace { id, root, bary })
+				.collect(),
+			None => Vec::new(),
+		};
 		PrModel {
 			particles: ps,
 			constraints: cs,
+			faces,
+		}
+	}
+
+	// clean up after constraints are removed by user
+	fn forget_constraints(&mut self, removed: &[i32]) {
+		self.pg.remove_edges(removed);
+		for &cid in removed.iter() {
+			self.topology.remove_edge(cid);
+			self.topology.remove_face(cid);
+		}
+		if let Some(remesh) = self.remesh.as_mut() {
+			for cid in removed.iter() {
+				remesh.faces.remove(cid);
+				remesh.thresholds.remove(cid);
+			}
+		}
+	}
+
+	// remove constraints and those depending on them
+	// return removed constraint ids
+	pub fn remove_constraints(&mut self, cids: Vec<i32>) -> Vec<i32> {
+		let removed = self.cg.remove_cascade(cids);
+		self.forget_constraints(&removed);
+		removed
+	}
+
+	// remove particles and every constraint on them
+	// return removed constraint ids
+	pub fn remove_particles(&mut self, ids: &[usize]) -> Vec<i32> {
+		let ids: HashSet<usize> = ids
+			.iter()
+			.copied()
+			.filter(|&id| self.pg.contains(id))
+			.collect();
+		let removed = self.cg.remove_particles(&ids);
+		self.forget_constraints(&removed);
+		self.pg.remove_particles(&ids);
+		if let Some(remesh) = self.remesh.as_mut() {
+			remesh.splits.retain(|split| !ids.contains(&split.pid));
+			remesh.prune(|id| self.cg.get(id).is_some());
+		}
+		removed
+	}
+
+	// model groups are numbered from 1 in the order of add_model
+	pub fn remove_model(&mut self, group: usize) -> Vec<i32> {
+		let ps = self.pg.particles();
+		let ids: Vec<usize> =
+			ps.ids().filter(|&id| ps.group(id) == group).collect();
+		self.remove_particles(&ids)
+	}
+
+	// solid particles in no constraint, e.g. left by breaking
+	pub fn remove_orphans(&mut self) -> Vec<i32> {
+		let constrained = self.cg.constrained_particles();
+		let ps = self.pg.particles();
+		let ids: Vec<usize> = ps
+			.ids()
+			.filter(|&id| ps.ty(id) == ParticleType::Solid)
+			.filter(|id| !constrained.contains(id))
+			.collect();
+		self.remove_particles(&ids)
+	}
+
+	// remove all particles, obstacles and fields are kept
+	pub fn clear(&mut self) -> Vec<i32> {
+		let ids: Vec<usize> = self.pg.particles().ids().collect();
+		self.remove_particles(&ids)
+	}
+
+	// complete world state, restoring it continues the run exactly
+	pub fn snapshot(&self) -> Vec<u8> {
+		bincode::serialize(self).unwrap()
+	}
+
+	pub fn from_snapshot(data: &[u8]) -> bincode::Result<Self> {
+		bincode::deserialize(data)
+	}
+
+	// play state, history, recorder and replay are kept,
+	// so a paused viewer stays paused
+	// constraint ids given out since the snapshot are not reused, so faces
+	// keyed by them(e.g. TextureIndexer) never land on another constraint
+	pub fn restore(&mut self, data: &[u8]) -> bincode::Result<()> {
+		let pworld = Self::from_snapshot(data)?;
+		let id_end = self.cg.id_end();
+		let forward_frames = self.forward_frames;
+		let history = self.history.take();
+		let recorder = self.recorder.take();
+		let replay = self.replay.take();
+		*self = pworld;
+		self.cg.reserve_ids(id_end);
+		self.forward_frames = forward_frames;
+		self.history = history;
+		self.recorder = recorder;
+		self.replay = replay;
+		Ok(())
+	}
+
+	// drag particle to pos, replacing its previous leash
+	pub fn control_particle(&mut self, id: usize, pos: V2) -> bool {
+		if !self.pg.contains(id) {
+			return false;
+		}
+		let con = LeashConstraint::new_with_pos(id, pos);
+		self.cg.control_particle(id, con);
+		true
+	}
+
+	pub fn frame(&self) -> usize {
+		self.frame
+	}
+
+	// first and last frame in history
+	pub fn history_range(&self) -> Option<[usize; 2]> {
+		self.history.as_ref()?.range()
+	}
+
+	// go back to a frame in history, false if it is not stored
+	// history is kept until the run continues from there
+	pub fn rewind(&mut self, frame: usize) -> bool {
+		let data = match self.history.as_ref().and_then(|h| h.get(frame)) {
+			Some(data) => data.to_vec(),
+			None => return false,
+		};
+		self.restore(&data).unwrap();
+		true
+	}
+
+	// go n stored frames forward(or back if negative)
+	pub fn scrub(&mut self, n: i32) -> bool {
+		match self.history.as_ref().and_then(|h| h.scrub(self.frame, n)) {
+			Some(frame) => self.rewind(frame),
+			None => false,
+		}
+	}
+
+	// called before each frame, so continuing from a rewound frame
+	// replaces the frames after it
+	fn record_history(&mut self) {
+		match self.history.as_ref() {
+			Some(history) if history.due(self.frame) => {}
+			_ => return,
+		}
+		let snapshot = self.snapshot();
+		self.history.as_mut().unwrap().record(self.frame, snapshot);
+	}
+
+	pub fn save_snapshot(&self, path: &str) -> std::io::Result<()> {
+		std::fs::write(path, self.snapshot())
+	}
+
+	pub fn load_snapshot(path: &str) -> bincode::Result<Self> {
+		Self::from_snapshot(&std::fs::read(path)?)
+	}
+
+	// current particle ids of the model, including those inserted by
+	// remeshing or fracture
+	pub fn model_particles(&self, group: usize) -> Vec<usize> {
+		let ps = self.pg.particles();
+		ps.ids().filter(|&id| ps.group(id) == group).collect()
+	}
+
+	// current constraint ids on particles of the model
+	pub fn model_constraints(&self, group: usize) -> Vec<i32> {
+		let ps = self.pg.particles();
+		self.cg
+			.pr_constraints()
+			.into_iter()
+			.filter(|c| c.particles.iter().any(|&id| ps.group(id) == group))
+			.map(|c| c.id)
+			.collect()
+	}
+
+	pub fn model_aabb(&self, group: usize) -> Option<[V2; 2]> {
+		let ps = self.pg.particles();
+		let mut ids = self.model_particles(group).into_iter();
+		let p0 = ps.pos(ids.next()?);
+		Some(ids.fold([p0, p0], |[min, max], id| {
+			let p = ps.pos(id);
+			[min.inf(&p), max.sup(&p)]
+		}))
+	}
+
+	// mass weighted average of f, fixed particles are ignored
+	// unless all particles are fixed
+	fn model_average(
+		&self,
+		group: usize,
+		f: impl Fn(usize) -> V2,
+	) -> Option<V2> {
+		let ps = self.pg.particles();
+		let ids = self.model_particles(group);
+		let weight = |id: usize| {
+			let imass = ps.imass(id);
+			if imass == 0f32 {
+				0f32
+			} else {
+				1.0 / imass
+			}
+		};
+		let mass: f32 = ids.iter().map(|&id| weight(id)).sum();
+		if ids.is_empty() {
+			None
+		} else if mass == 0f32 {
+			let sum = ids.iter().fold(V2::new(0., 0.), |s, &id| s + f(id));
+			Some(sum / ids.len() as f32)
+		} else {
+			let sum = ids
+				.iter()
+				.fold(V2::new(0., 0.), |s, &id| s + f(id) * weight(id));
+			Some(sum / mass)
+		}
+	}
+
+	pub fn model_center(&self, group: usize) -> Option<V2> {
+		let ps = self.pg.particles();
+		self.model_average(group, |id| ps.pos(id))
+	}
+
+	pub fn model_velocity(&self, group: usize) -> Option<V2> {
+		let ps = self.pg.particles();
+		self.model_average(group, |id| {
+			let pdt = ps.pdt(id);
+			if pdt == 0f32 {
+				V2::new(0., 0.)
+			} else {
+				ps.dpos(id) / pdt
+			}
+		})
+	}
+
+	// velocity is kept
+	pub fn translate_model(&mut self, group: usize, d: V2) {
+		for id in self.model_particles(group) {
+			self.pg.particles_mut().offset_pos(id, d);
+		}
+	}
+
+	// around center of mass, velocity is rotated too
+	pub fn rotate_model(&mut self, group: usize, angle: f32) {
+		let c = match self.model_center(group) {
+			Some(c) => c,
+			None => return,
+		};
+		let (sin, cos) = angle.sin_cos();
+		let rotate = |p: V2| {
+			let q = p - c;
+			c + V2::new(cos * q[0] - sin * q[1], sin * q[0] + cos * q[1])
+		};
+		for id in self.model_particles(group) {
+			let ps = self.pg.particles_mut();
+			let (pos, ppos) = (rotate(ps.pos(id)), rotate(ps.ppos(id)));
+			ps.set_pos(id, pos);
+			ps.set_ppos(id, ppos);
+		}
+	}
+
+	// around center of mass, rest lengths and areas are scaled
+	// particle radius is not changed
+	pub fn scale_model(&mut self, group: usize, k: f32) {
+		let c = match self.model_center(group) {
+			Some(c) => c,
+			None => return,
+		};
+		let ids = self.model_particles(group);
+		for &id in ids.iter() {
+			let ps = self.pg.particles_mut();
+			let dp = ps.dpos(id);
+			let pos = c + (ps.pos(id) - c) * k;
+			ps.set_pos(id, pos);
+			ps.set_ppos(id, pos - dp);
+		}
+		let ids: HashSet<usize> = ids.into_iter().collect();
+		if let Some(remesh) = self.remesh.as_mut() {
+			// constraints waiting for coarsening
+			for split in remesh.splits.iter_mut() {
+				if ids.contains(&split.pid) {
+					split.edge.1.scale(k);
+					for face in split.faces.iter_mut() {
+						face.1.scale(k);
+					}
+				}
+			}
+		}
+		self.cg.scale(&ids, k);
+	}
+
+	// change velocity of all free particles by dv
+	pub fn impulse_model(&mut self, group: usize, dv: V2) {
+		for id in self.model_particles(group) {
+			let ps = self.pg.particles_mut();
+			if ps.imass(id) == 0f32 {
+				continue;
+			}
+			let pdt = ps.pdt(id);
+			let t = if pdt == 0f32 { self.dt } else { pdt };
+			let ppos = ps.ppos(id) - dv * t;
+			ps.set_ppos(id, ppos);
+		}
+	}
+
+	// gravity and force fields to accelerations, before integration
+	fn apply_forces(&mut self, dt: f32) {
+		self.time += dt;
+		let ps = self.pg.particles();
+		let accels: Vec<(usize, V2)> = ps
+			.ids()
+			.map(|id| {
+				let accel = self
+					.force_fields
+					.iter()
+					.filter(|field| field.targets(ps, id))
+					.map(|field| field.accel(ps, id, self.time, dt))
+					.fold(self.gravity, |a, b| a + b);
+				(id, accel)
+			})
+			.collect();
+		let ps = self.pg.particles_mut();
+		for (id, accel) in accels.into_iter() {
+			ps.set_accel(id, accel);
 		}
 	}
 
@@ -139,15 +1070,40 @@ impl PWorld {
 		if dt == 0f32 {
 			return;
 		}
-		self.cg.pre_iteration();
+		// before pre_iteration, which resets new constraints
+		if self.remesh.is_some() {
+			self.coarsen(dt);
+			self.refine();
+		}
+		let removed = self.cg.pre_iteration(self.pg.particles());
+		self.pg.remove_edges(&removed);
+		if self.fracture {
+			self.fracture(&removed);
+		} else {
+			for &cid in removed.iter() {
+				self.topology.remove_edge(cid);
+				self.topology.remove_face(cid);
+			}
+		}
+		if let Some(remesh) = self.remesh.as_mut() {
+			for cid in removed.iter() {
+				remesh.faces.remove(cid);
+				remesh.thresholds.remove(cid);
+			}
+		}
 		timer.lap();
+		self.apply_forces(dt);
 		self.pg.update(dt);
 		timer.lap();
 		self.cg.set_tmp_constraints(self.pg.collision_constraints());
 		timer.lap();
 		for _ in 0..iteration {
-			self.cg.solve_constraints(dt);
+			if self.hpbd {
+				self.cg.solve_levels(dt, self.pg.particles());
+			}
+			self.cg.solve_constraints(dt, self.pg.particles());
 		}
+		self.cg.update_velocity(self.pg.particles_mut());
 		timer.lap();
 		if self.print_perf {
 			eprintln!("{:?}", timer.get_laps());
@@ -155,9 +1111,132 @@ impl PWorld {
 	}
 
 	pub fn run(&mut self) {
+		self.record_history();
 		for _ in 0..self.ppr {
 			self.update_frame(self.dt, self.iteration);
 		}
+		self.frame += 1;
+	}
+
+	// advance t seconds, in equal steps no longer than dt
+	pub fn run_for(&mut self, t: f32) {
+		if t <= 0f32 {
+			return;
+		}
+		self.record_history();
+		let n = (t / self.dt).ceil().max(1.0) as usize;
+		for _ in 0..n {
+			self.update_frame(t / n as f32, self.iteration);
+		}
+		self.frame += 1;
+	}
+
+	// return true if an update should be sent
+	fn apply_message(
+		&mut self,
+		msg: ControllerMessage,
+		tx: &Sender<UserEvent>,
+	) -> bool {
+		let mut send_update = false;
+		match msg {
+			ControllerMessage::TogglePause => {
+				if self.forward_frames == 0 {
+					self.forward_frames = -1;
+				} else {
+					self.forward_frames = 0;
+				}
+			}
+			ControllerMessage::FrameForward => {
+				if self.forward_frames == 0 {
+					self.forward_frames += 1;
+				}
+			}
+			ControllerMessage::ControlParticle(id, pos) => {
+				if !self.control_particle(id, pos.into()) {
+					eprintln!("ERROR: control particle id {} is bad", id);
+				}
+			}
+			ControllerMessage::UncontrolParticle(id) => {
+				self.cg.uncontrol_particle(id);
+			}
+			ControllerMessage::SetGravity(gravity) => {
+				self.set_gravity(gravity.into());
+			}
+			ControllerMessage::AddModel(model, offset, payload) => {
+				let handle = self.add_model(model, offset.into());
+				// sent before the update containing the model
+				let event = UserEvent::ModelAdded {
+					group: handle.group,
+					constraints: handle.constraints,
+					payload,
+				};
+				tx.send(event).unwrap();
+				send_update = true;
+			}
+			ControllerMessage::RemoveParticle(id) => {
+				self.remove_particles(&[id]);
+				send_update = true;
+			}
+			ControllerMessage::RemoveConstraint(cid) => {
+				self.remove_constraints(vec![cid]);
+				send_update = true;
+			}
+			ControllerMessage::RemoveModel(group) => {
+				self.remove_model(group);
+				send_update = true;
+			}
+			ControllerMessage::RemoveOrphans => {
+				self.remove_orphans();
+				send_update = true;
+			}
+			ControllerMessage::Clear => {
+				self.clear();
+				send_update = true;
+			}
+			ControllerMessage::Rewind(frame) => {
+				if self.rewind(frame) {
+					self.forward_frames = 0;
+					send_update = true;
+				} else {
+					eprintln!("ERROR: frame {} is not in history", frame);
+				}
+			}
+			ControllerMessage::Scrub(n) => {
+				if self.scrub(n) {
+					self.forward_frames = 0;
+					send_update = true;
+				}
+			}
+			ControllerMessage::ResumeFrom(frame) => {
+				if self.rewind(frame) {
+					self.forward_frames = -1;
+				} else {
+					eprintln!("ERROR: frame {} is not in history", frame);
+				}
+			}
+			ControllerMessage::SaveSnapshot(path) => {
+				match self.save_snapshot(&path) {
+					Ok(()) => {
+						eprintln!("INFO: snapshot saved: {}", path)
+					}
+					Err(e) => {
+						eprintln!("ERROR: save snapshot {} failed: {}", path, e)
+					}
+				}
+			}
+			ControllerMessage::LoadSnapshot(path) => {
+				let result = std::fs::read(&path)
+					.map_err(bincode::Error::from)
+					.and_then(|data| self.restore(&data));
+				match result {
+					Ok(()) => send_update = true,
+					Err(e) => {
+						eprintln!("ERROR: load snapshot {} failed: {}", path, e)
+					}
+				}
+			}
+		}
+		send_update
 	}
 
 	pub fn run_thread(
@@ -167,15 +1246,35 @@ impl PWorld {
 	) {
 		let rtime = self.dt * self.ppr as f32 * self.time_scale;
 		let mut first_frame = true;
+		let mut last_frame = Instant::now();
+		let mut send_update = false;
+		if self.recorder.is_some() {
+			let snapshot = self.snapshot();
+			let recorder = self.recorder.as_mut().unwrap();
+			if let Err(e) = recorder.start(&snapshot) {
+				eprintln!("ERROR: start recording failed: {}", e);
+				self.recorder = None;
+			}
+		}
 		loop {
 			let mut timer = Timer::default();
-			if self.forward_frames != 0 {
-				self.forward_frames -= 1;
-				if !first_frame {
-					self.run();
-				} else {
+			if self.forward_frames != 0 || send_update {
+				let elapsed = last_frame.elapsed().as_secs_f32();
+				last_frame = Instant::now();
+				if first_frame || self.forward_frames == 0 {
 					first_frame = false;
+				} else if self.realtime && self.forward_frames < 0 {
+					// drop time when falling too far behind
+					let t = (elapsed / self.time_scale)
+						.min(4.0 * self.dt * self.ppr as f32);
+					self.run_for(t);
+				} else {
+					self.run();
+				}
+				if self.forward_frames != 0 {
+					self.forward_frames -= 1;
 				}
+				send_update = false;
 				let model = self.pr_model();
 				let (dt, _) = timer.lap();
 				let event = UserEvent::Update(
@@ -184,46 +1283,416 @@ impl PWorld {
 						load: dt / rtime,
 						particle_len: self.pg.len(),
 						constraint_len: self.cg.len(),
+						frame: self.frame,
+						history: self.history_range(),
 					},
 				);
 				tx.send(event).unwrap();
 			}
 
-			while let Ok(msg) = rx.try_recv() {
-				match msg {
-					ControllerMessage::TogglePause => {
-						if self.forward_frames == 0 {
-							self.forward_frames = -1;
-						} else {
-							self.forward_frames = 0;
-						}
-					}
-					ControllerMessage::FrameForward => {
-						if self.forward_frames == 0 {
-							self.forward_frames += 1;
-						}
-					}
-					ControllerMessage::ControlParticle(id, pos) => {
-						if let Some(pref) = self.pg.get_pref(id) {
-							let con =
-								LeashConstraint::new_with_pos(pref, pos.into());
-							self.cg.control_particle(id, con);
-						} else {
-							eprintln!(
-								"ERROR: control particle id {} is bad",
-								id
-							);
-						}
-					}
-					ControllerMessage::UncontrolParticle(id) => {
-						self.cg.uncontrol_particle(id);
+			loop {
+				// recorded messages go first, live input is still accepted
+				let frame = self.frame;
+				let msg = match self.replay.as_mut().and_then(|r| r.next(frame))
+				{
+					Some(msg) => msg,
+					None => match rx.try_recv() {
+						Ok(msg) => msg,
+						Err(_) => break,
+					},
+				};
+				if let Some(recorder) = self.recorder.as_mut() {
+					if let Err(e) = recorder.record(frame, &msg) {
+						eprintln!("ERROR: record message failed: {}", e);
+						self.recorder = None;
 					}
 				}
+				send_update |= self.apply_message(msg, &tx);
 			}
 			let (_, dt_a) = timer.lap();
-			if dt_a < rtime {
+			if self.forward_frames == 0 {
+				// paused time is not simulated
+				last_frame = Instant::now();
+			}
+			if self.realtime && self.forward_frames != 0 {
+				// wait for at least one step
+				let elapsed = last_frame.elapsed().as_secs_f32();
+				let min_time = self.dt * self.time_scale;
+				if elapsed < min_time {
+					timer.sleep(min_time - elapsed);
+				}
+			} else if dt_a < rtime {
 				timer.sleep(rtime - dt_a);
 			}
 		}
 	}
 }
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::constraint::distance::{
+		DistanceConstraintTemplate, DistanceConstraintType,
+	};
+	use crate::particle::ParticleTemplate;
+
+	// n * n particles, edges break when stretched
+	fn grid(n: usize) -> PhysicalModel {
+		let s = 0.08;
+		let mut model = PhysicalModel::default();
+		for i in 0..n {
+			for j in 0..n {
+				let imass = if i == 0 { 0.0 } else { 1.0 };
+				let pos = V2::new(i as f32, j as f32) * s;
+				let p = ParticleTemplate::new(imass, pos, s / 2.0);
+				model.particles.push(p);
+			}
+		}
+		for a in 0..n * n {
+			for b in [a + 1, a + n] {
+				if b >= n * n || (b == a + 1 && b % n == 0) {
+					continue;
+				}
+				model.constraints.push(ConstraintTemplate::Distance(
+					DistanceConstraintTemplate {
+						ps: vec![a, b],
+						l0: s,
+						compliance: 1e-5,
+						ty: DistanceConstraintType::Normal,
+						break_range: Some([0.0, 1.01]),
+					},
+				));
+			}
+		}
+		model
+	}
+
+	// free right triangle with unit legs, in a world without gravity
+	fn triangle() -> (PWorld, ModelHandle) {
+		let mut model = PhysicalModel::default();
+		for pos in [[0., 0.], [1., 0.], [0., 1.]] {
+			let p = ParticleTemplate::new(1.0, V2::from(pos), 0.04);
+			model.particles.push(p);
+		}
+		for ps in [[0, 1], [1, 2], [2, 0]] {
+			let [p0, p1] = ps.map(|i| model.particles[i].pos);
+			model.constraints.push(ConstraintTemplate::Distance(
+				DistanceConstraintTemplate {
+					ps: ps.to_vec(),
+					l0: (p0 - p1).magnitude(),
+					compliance: 0.0,
+					ty: DistanceConstraintType::Normal,
+					break_range: None,
+				},
+			));
+		}
+		let mut pworld = PWorld::default().with_gravity(V2::zeros());
+		let handle = pworld.add_model(model, V2::zeros());
+		(pworld, handle)
+	}
+
+	#[test]
+	fn test_snapshot() {
+		let mut pworld = PWorld::default();
+		pworld.add_model(grid(6), V2::new(0., 0.));
+		pworld.add_model(grid(6), V2::new(0.2, -0.5));
+		for _ in 0..20 {
+			pworld.run();
+		}
+		let snapshot = pworld.snapshot();
+		for _ in 0..20 {
+			pworld.run();
+		}
+		let mut restored = PWorld::from_snapshot(&snapshot).unwrap();
+		for _ in 0..20 {
+			restored.run();
+		}
+		let (m1, m2) = (pworld.pr_model(), restored.pr_model());
+		assert_eq!(m1.constraints.len(), m2.constraints.len());
+		assert_eq!(m1.particles.len(), m2.particles.len());
+		for (id, p) in m1.particles.iter() {
+			assert_eq!(p.pos, m2.particles[id].pos);
+		}
+	}
+
+	#[test]
+	fn test_restore_keeps_ids() {
+		let mut pworld = PWorld::default();
+		pworld.add_model(grid(3), V2::new(0., 0.));
+		let snapshot = pworld.snapshot();
+		let first = pworld.add_model(grid(3), V2::new(0., -1.));
+		pworld.restore(&snapshot).unwrap();
+		let second = pworld.add_model(grid(3), V2::new(0., -1.));
+		let min = second.constraints.iter().min().unwrap();
+		assert!(first.constraints.iter().all(|cid| cid < min));
+	}
+
+	#[test]
+	fn test_rewind_past_add() {
+		let mut pworld = PWorld::default().with_history(1, 10);
+		pworld.add_model(grid(3), V2::new(0., 0.));
+		pworld.run();
+		let first = pworld.add_model(grid(3), V2::new(0., -1.));
+		pworld.run();
+		pworld.run();
+		assert!(pworld.rewind(1));
+		assert!(pworld.scrub(-1));
+		assert_eq!(pworld.frame(), 0);
+		let second = pworld.add_model(grid(3), V2::new(0., -1.));
+		let min = second.constraints.iter().min().unwrap();
+		assert!(first.constraints.iter().all(|cid| cid < min));
+		// the undone model is gone, the new one runs
+		assert_eq!(pworld.pr_model().particles.len(), 18);
+		pworld.run();
+		assert_eq!(pworld.history_range(), Some([0, 0]));
+	}
+
+	// two triangles on edge 0-1, which breaks at once
+	#[test]
+	fn test_fracture_split() {
+		use crate::constraint::shape_match::ShapeMatchConstraintTemplate;
+		use crate::constraint::volume::VolumeConstraintTemplate;
+		let mut model = PhysicalModel::default();
+		for pos in [[0., 0.], [1., 0.], [0.5, 1.], [0.5, -1.]] {
+			let p = ParticleTemplate::new(1.0, V2::from(pos), 0.1);
+			model.particles.push(p);
+		}
+		for ps in [[0, 1], [0, 2], [1, 2], [0, 3], [1, 3]] {
+			let range = if ps == [0, 1] { [0.0, 0.5] } else { [0.0, 2.0] };
+			let [p0, p1] = ps.map(|i| model.particles[i].pos);
+			model.constraints.push(ConstraintTemplate::Distance(
+				DistanceConstraintTemplate {
+					ps: ps.to_vec(),
+					l0: (p0 - p1).magnitude(),
+					compliance: 1e-5,
+					ty: DistanceConstraintType::Normal,
+					break_range: Some(range),
+				},
+			));
+		}
+		for ps in [[0, 1, 2], [0, 1, 3]] {
+			model.constraints.push(ConstraintTemplate::Volume(
+				VolumeConstraintTemplate {
+					ps: ps.to_vec(),
+					compliance: 1e-7,
+				},
+			));
+		}
+		model.constraints.push(ConstraintTemplate::ShapeMatch(
+			ShapeMatchConstraintTemplate {
+				ps: vec![0, 1, 2, 3],
+				compliance: 0.0,
+			},
+		));
+		let mut pworld =
+			PWorld::default().with_fracture().with_gravity(V2::zeros());
+		let handle = pworld.add_model(model, V2::zeros());
+		pworld.run();
+		// 0 and 1 are split, each copy has half of the mass
+		let ps = pworld.pg.particles();
+		assert_eq!(ps.len(), 6);
+		for id in [0, 1, 4, 5] {
+			assert_eq!(ps.imass(handle.particles[0] + id), 2.0);
+		}
+		assert!(pworld.cg.get(handle.constraints[7]).is_none());
+	}
+
+	// halves of a split edge keep its strain, they are not split again
+	// until the strain grows by refine_strain, mass is kept by splits
+	// and merges
+	#[test]
+	fn test_refine_once() {
+		use crate::constraint::volume::VolumeConstraintTemplate;
+		let s = 0.08;
+		let mut model = PhysicalModel::default();
+		for pos in [[0., 0.], [s, 0.], [0., s], [s, s]] {
+			let imass = if pos[0] == 0.0 { 0.0 } else { 1.0 };
+			let p = ParticleTemplate::new(imass, V2::from(pos), s / 2.0);
+			model.particles.push(p);
+		}
+		for ps in [[0, 1], [2, 3], [0, 2], [1, 3], [0, 3]] {
+			let [p0, p1] = ps.map(|i| model.particles[i].pos);
+			model.constraints.push(ConstraintTemplate::Distance(
+				DistanceConstraintTemplate {
+					ps: ps.to_vec(),
+					l0: (p0 - p1).magnitude(),
+					compliance: 1e-5,
+					ty: DistanceConstraintType::Normal,
+					break_range: None,
+				},
+			));
+		}
+		for ps in [[0, 1, 3], [0, 3, 2]] {
+			model.constraints.push(ConstraintTemplate::Volume(
+				VolumeConstraintTemplate {
+					ps: ps.to_vec(),
+					compliance: 1e-7,
+				},
+			));
+		}
+		let mut pworld = PWorld::default()
+			.with_gravity(V2::zeros())
+			.with_remesh(Remesh::default());
+		let handle = pworld.add_model(model, V2::zeros());
+		// stretched by 25% along x
+		for idx in [1, 3] {
+			let id = handle.particles[idx];
+			let y = pworld.pg.particles().pos(id)[1];
+			pworld.control_particle(id, V2::new(1.25 * s, y));
+		}
+		for _ in 0..20 {
+			pworld.run();
+		}
+		// both long sides and the diagonal are split once
+		assert_eq!(handle.particle_ids(&pworld).len(), 7);
+		// fixed particles have no mass to give
+		let mass = |pworld: &PWorld| -> f32 {
+			let ps = pworld.pg.particles();
+			handle
+				.particle_ids(pworld)
+				.into_iter()
+				.filter(|&id| ps.imass(id) != 0.0)
+				.map(|id| 1.0 / ps.imass(id))
+				.sum()
+		};
+		assert!((mass(&pworld) - 2.0).abs() < 1e-5, "{}", mass(&pworld));
+		for idx in [1, 3] {
+			pworld.cg.uncontrol_particle(handle.particles[idx]);
+		}
+		for _ in 0..300 {
+			pworld.run();
+		}
+		// merged back when released
+		assert_eq!(handle.particle_ids(&pworld).len(), 4);
+		assert!((mass(&pworld) - 2.0).abs() < 1e-5, "{}", mass(&pworld));
+	}
+
+	// a particle that tunnelled into a model is pushed back out of the
+	// outline, not further in
+	#[test]
+	fn test_edge_tunnel() {
+		use crate::constraint::volume::VolumeConstraintTemplate;
+		let particle = |x: f32, y: f32, imass: f32| {
+			ParticleTemplate::new(imass, V2::new(x, y), 0.04)
+		};
+		let mut model = PhysicalModel::default();
+		for [x, y] in [[0., 0.], [1., 0.], [0.5, 1.]] {
+			model.particles.push(particle(x, y, 0.0));
+		}
+		for ps in [[0, 1], [1, 2], [2, 0]] {
+			let [p0, p1] = ps.map(|i| model.particles[i].pos);
+			model.constraints.push(ConstraintTemplate::Distance(
+				DistanceConstraintTemplate {
+					ps: ps.to_vec(),
+					l0: (p0 - p1).magnitude(),
+					compliance: 0.0,
+					ty: DistanceConstraintType::Normal,
+					break_range: None,
+				},
+			));
+		}
+		model.constraints.push(ConstraintTemplate::Volume(
+			VolumeConstraintTemplate {
+				ps: vec![0, 1, 2],
+				compliance: 0.0,
+			},
+		));
+		let mut pworld = PWorld::default().with_gravity(V2::zeros());
+		pworld.add_model(model, V2::zeros());
+		let mut ball = PhysicalModel::default();
+		ball.particles.push(particle(0.5, 0.01, 1.0));
+		let handle = pworld.add_model(ball, V2::zeros());
+		for _ in 0..5 {
+			pworld.run();
+		}
+		let y = pworld.pg.particles().pos(handle.particles[0])[1];
+		assert!(y < -0.07, "{}", y);
+	}
+
+	// settled height of a fluid column does not depend on particle mass
+	#[test]
+	fn test_fluid_mass() {
+		use crate::fluid_model::FluidModelBuilder;
+		let height = |imass: f32| {
+			let posbox = Posbox {
+				xmin: 0.0,
+				xmax: 0.8,
+				ymin: -2.0,
+				ymax: 0.0,
+			};
+			let mut pworld = PWorld::default().with_posbox(posbox);
+			let mut builder = FluidModelBuilder::default().with_imass(imass);
+			builder.add_rect(V2::new(0.02, -0.8), [20, 20]);
+			pworld.add_model(builder.build_physical_model(), V2::zeros());
+			for _ in 0..100 {
+				pworld.run();
+			}
+			let [min, _] = pworld.model_aabb(1).unwrap();
+			-min[1]
+		};
+		let h1 = height(1.0);
+		let h2 = height(0.5);
+		// 20 rows spaced by 0.04
+		assert!(h1 > 0.6 && h1 < 0.9);
+		assert!((h1 - h2).abs() < 0.05);
+	}
+
+	// velocity is kept, so a model at rest stays at rest
+	#[test]
+	fn test_translate() {
+		let (mut pworld, handle) = triangle();
+		handle.translate(&mut pworld, V2::new(2., -1.));
+		pworld.run();
+		let pos = pworld.pg.particles().pos(handle.particles[1]);
+		assert!((pos - V2::new(3., -1.)).magnitude() < 1e-5);
+		let v = handle.velocity(&pworld).unwrap();
+		assert!(v.magnitude() < 1e-3, "{:?}", v);
+	}
+
+	#[test]
+	fn test_rotate() {
+		let (mut pworld, handle) = triangle();
+		handle.impulse(&mut pworld, V2::new(1., 0.));
+		pworld.run();
+		let c = handle.center(&pworld).unwrap();
+		let p0 = pworld.pg.particles().pos(handle.particles[1]);
+		handle.rotate(&mut pworld, std::f32::consts::FRAC_PI_2);
+		let p = pworld.pg.particles().pos(handle.particles[1]);
+		let q = p0 - c;
+		assert!((p - c - V2::new(-q[1], q[0])).magnitude() < 1e-5);
+		let v = handle.velocity(&pworld).unwrap();
+		assert!((v - V2::new(0., 1.)).magnitude() < 1e-3, "{:?}", v);
+	}
+
+	// rest lengths are scaled, so the scaled shape is kept
+	#[test]
+	fn test_scale() {
+		let (mut pworld, handle) = triangle();
+		let c = handle.center(&pworld).unwrap();
+		handle.scale(&mut pworld, 2.0);
+		for _ in 0..30 {
+			pworld.run();
+		}
+		let ps = pworld.pg.particles();
+		let [p0, p1, p2] = [0, 1, 2].map(|i| ps.pos(handle.particles[i]));
+		assert!(((p0 - p1).magnitude() - 2.0).abs() < 1e-3);
+		assert!(((p1 - p2).magnitude() - 8f32.sqrt()).abs() < 1e-3);
+		let dc = handle.center(&pworld).unwrap() - c;
+		assert!(dc.magnitude() < 1e-3);
+	}
+
+	#[test]
+	fn test_impulse() {
+		let (mut pworld, handle) = triangle();
+		handle.impulse(&mut pworld, V2::new(0.5, -1.));
+		let c = handle.center(&pworld).unwrap();
+		for _ in 0..10 {
+			pworld.run();
+		}
+		let v = handle.velocity(&pworld).unwrap();
+		assert!((v - V2::new(0.5, -1.)).magnitude() < 1e-3, "{:?}", v);
+		let t = 10.0 * pworld.dt * pworld.ppr as f32;
+		let dc = handle.center(&pworld).unwrap() - c;
+		assert!((dc - V2::new(0.5, -1.) * t).magnitude() < 1e-3);
+	}
+}
@@ -0,0 +1,171 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, ErrorKind, Write};
+
+use crate::controller_message::ControllerMessage;
+
+// log file: world snapshot when run_thread starts,
+// then (frame, message) for each message in the order they are applied
+pub struct Recorder {
+	file: BufWriter<File>,
+	started: bool,
+}
+
+impl Recorder {
+	pub fn create(path: &str) -> std::io::Result<Self> {
+		Ok(Self {
+			file: BufWriter::new(File::create(path)?),
+			started: false,
+		})
+	}
+
+	// world the messages are applied to, written once
+	pub fn start(&mut self, snapshot: &[u8]) -> bincode::Result<()> {
+		if self.started {
+			return Ok(());
+		}
+		self.started = true;
+		bincode::serialize_into(&mut self.file, snapshot)?;
+		self.file.flush()?;
+		Ok(())
+	}
+
+	pub fn record(
+		&mut self,
+		frame: usize,
+		msg: &ControllerMessage,
+	) -> bincode::Result<()> {
+		bincode::serialize_into(&mut self.file, &(frame, msg))?;
+		// flushed so the log survives a crash
+		self.file.flush()?;
+		Ok(())
+	}
+}
+
+pub struct Replay {
+	pub snapshot: Vec<u8>,
+	entries: VecDeque<(usize, ControllerMessage)>,
+}
+
+impl Replay {
+	// a truncated last entry, e.g. after a crash, is dropped
+	pub fn load(path: &str) -> bincode::Result<Self> {
+		let mut reader = BufReader::new(File::open(path)?);
+		let snapshot = bincode::deserialize_from(&mut reader)?;
+		let mut entries = VecDeque::new();
+		loop {
+			match bincode::deserialize_from(&mut reader) {
+				Ok(entry) => entries.push_back(entry),
+				Err(e) => match *e {
+					bincode::ErrorKind::Io(ref io)
+						if io.kind() == ErrorKind::UnexpectedEof =>
+					{
+						break
+					}
+					_ => return Err(e),
+				},
+			}
+		}
+		Ok(Self { snapshot, entries })
+	}
+
+	// next message applied at frame or before, call until None to drain
+	// a message whose frame was passed is applied late, not dropped
+	pub fn next(&mut self, frame: usize) -> Option<ControllerMessage> {
+		match self.entries.front() {
+			Some((f, _)) if *f <= frame => {
+				let (f, msg) = self.entries.pop_front()?;
+				if f < frame {
+					eprintln!(
+						"WARN: message of frame {} replayed at {}",
+						f, frame
+					);
+				}
+				Some(msg)
+			}
+			_ => None,
+		}
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.entries.is_empty()
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::particle::ParticleTemplate;
+	use crate::physical_model::PhysicalModel;
+	use crate::V2;
+
+	#[test]
+	fn test_replay() {
+		let name = format!("psva2d_test_replay_{}.bin", std::process::id());
+		let path = std::env::temp_dir().join(name);
+		let path = path.to_str().unwrap();
+		let mut recorder = Recorder::create(path).unwrap();
+		recorder.start(&[1, 2, 3]).unwrap();
+		recorder.record(0, &ControllerMessage::TogglePause).unwrap();
+		recorder.record(5, &ControllerMessage::Scrub(-1)).unwrap();
+		recorder
+			.record(3, &ControllerMessage::RemoveModel(2))
+			.unwrap();
+		let mut model = PhysicalModel::default();
+		let p = ParticleTemplate::new(1.0, V2::new(1., 2.), 0.04);
+		model.particles.push(p);
+		let msg = ControllerMessage::AddModel(model, [0., -1.], vec![7, 8, 9]);
+		recorder.record(3, &msg).unwrap();
+		drop(recorder);
+		let mut replay = Replay::load(path).unwrap();
+		std::fs::remove_file(path).unwrap();
+		assert_eq!(replay.snapshot, vec![1, 2, 3]);
+		assert!(matches!(
+			replay.next(0),
+			Some(ControllerMessage::TogglePause)
+		));
+		assert!(replay.next(0).is_none());
+		// frames go back after a rewind
+		assert!(matches!(replay.next(5), Some(ControllerMessage::Scrub(-1))));
+		assert!(replay.next(2).is_none());
+		assert!(matches!(
+			replay.next(3),
+			Some(ControllerMessage::RemoveModel(2))
+		));
+		match replay.next(3) {
+			Some(ControllerMessage::AddModel(model, offset, payload)) => {
+				assert_eq!(model.particles[0].pos, V2::new(1., 2.));
+				assert_eq!(offset, [0., -1.]);
+				assert_eq!(payload, vec![7, 8, 9]);
+			}
+			_ => panic!("AddModel is not replayed"),
+		}
+		assert!(replay.is_empty());
+	}
+
+	// messages of a skipped frame are applied at the next one
+	#[test]
+	fn test_skipped_frame() {
+		let name = format!("psva2d_test_skip_{}.bin", std::process::id());
+		let path = std::env::temp_dir().join(name);
+		let path = path.to_str().unwrap();
+		let mut recorder = Recorder::create(path).unwrap();
+		recorder.start(&[]).unwrap();
+		recorder.record(2, &ControllerMessage::TogglePause).unwrap();
+		recorder.record(3, &ControllerMessage::Scrub(1)).unwrap();
+		recorder.record(6, &ControllerMessage::Scrub(2)).unwrap();
+		drop(recorder);
+		let mut replay = Replay::load(path).unwrap();
+		std::fs::remove_file(path).unwrap();
+		assert!(replay.next(1).is_none());
+		// frames 2 and 3 are passed
+		assert!(matches!(
+			replay.next(4),
+			Some(ControllerMessage::TogglePause)
+		));
+		assert!(matches!(replay.next(4), Some(ControllerMessage::Scrub(1))));
+		assert!(replay.next(4).is_none());
+		assert!(matches!(replay.next(6), Some(ControllerMessage::Scrub(2))));
+		assert!(replay.is_empty());
+	}
+}
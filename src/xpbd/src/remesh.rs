@@ -0,0 +1,89 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::constraint::CRef;
+use serde::{Deserialize, Serialize};
+
+// an edge split at midpoint, kept for coarsening
+#[derive(Serialize, Deserialize)]
+pub struct Split {
+	// inserted particle
+	pub pid: usize,
+	// removed edge and faces, with their constraint ids
+	pub edge: (i32, CRef, [usize; 2]),
+	pub faces: Vec<(i32, CRef, [usize; 3])>,
+	// constraints created by the split, all removed on coarsening
+	pub new_edges: Vec<(i32, [usize; 2])>,
+	pub new_faces: Vec<(i32, [usize; 3])>,
+	// mass moved from each edge end to the inserted particle
+	pub moved: [f32; 2],
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Remesh {
+	// split edge when |l / l0 - 1| is larger
+	pub refine_strain: f32,
+	// merge split when strain of all new edges is smaller
+	pub coarsen_strain: f32,
+	// and particles move slower than this
+	pub rest_speed: f32,
+	// do not split edge shorter than this
+	pub min_length: f32,
+	pub(crate) splits: Vec<Split>,
+	// derived face id -> root face id, barycentric coords of corners
+	pub(crate) faces: HashMap<i32, (i32, [[f32; 3]; 3])>,
+	// refine strain of edges created by a split, above the strain of the
+	// split edge, so its halves are not split again at once
+	pub(crate) thresholds: HashMap<i32, f32>,
+}
+
+impl Default for Remesh {
+	fn default() -> Self {
+		Self {
+			refine_strain: 0.1,
+			coarsen_strain: 0.02,
+			rest_speed: 0.2,
+			min_length: 0.02,
+			splits: Vec::new(),
+			faces: HashMap::new(),
+			thresholds: HashMap::new(),
+		}
+	}
+}
+
+impl Remesh {
+	pub fn with_strain(mut self, refine: f32, coarsen: f32) -> Self {
+		assert!(coarsen < refine);
+		self.refine_strain = refine;
+		self.coarsen_strain = coarsen;
+		self
+	}
+
+	pub fn with_rest_speed(mut self, rest_speed: f32) -> Self {
+		self.rest_speed = rest_speed;
+		self
+	}
+
+	pub fn with_min_length(mut self, min_length: f32) -> Self {
+		self.min_length = min_length;
+		self
+	}
+
+	// drop entries of constraints neither alive nor kept by a split
+	pub(crate) fn prune(&mut self, alive: impl Fn(i32) -> bool) {
+		let kept: HashSet<i32> = self
+			.splits
+			.iter()
+			.flat_map(|split| split.faces.iter().map(|x| x.0))
+			.collect();
+		self.faces.retain(|id, _| alive(*id) || kept.contains(id));
+		self.thresholds.retain(|id, _| alive(*id));
+	}
+
+	// root face and barycentric coords relative to it
+	pub(crate) fn root_of(&self, cid: i32) -> (i32, [[f32; 3]; 3]) {
+		match self.faces.get(&cid) {
+			Some(x) => *x,
+			None => (cid, [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]]),
+		}
+	}
+}
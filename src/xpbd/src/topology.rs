@@ -0,0 +1,236 @@
+use serde::{Deserialize, Serialize};
+
+// iteration order must not vary between runs
+type Map<K, V> = fnv::FnvHashMap<K, V>;
+
+// face and edge constraints of models, for fracture and remeshing
+// all ids are particle ids, and keys are constraint ids
+#[derive(Default, Serialize, Deserialize)]
+pub struct Topology {
+	faces: Map<i32, [usize; 3]>,
+	vfaces: Map<usize, Vec<i32>>,
+	// sorted particle ids
+	edges: Map<[usize; 2], i32>,
+	edge_ps: Map<i32, [usize; 2]>,
+}
+
+fn sorted(mut ps: [usize; 2]) -> [usize; 2] {
+	ps.sort_unstable();
+	ps
+}
+
+impl Topology {
+	pub fn add_face(&mut self, cid: i32, ps: [usize; 3]) {
+		for p in ps.iter() {
+			self.vfaces.entry(*p).or_default().push(cid);
+		}
+		assert!(self.faces.insert(cid, ps).is_none());
+	}
+
+	pub fn add_edge(&mut self, cid: i32, ps: [usize; 2]) {
+		let ps = sorted(ps);
+		assert!(self.edges.insert(ps, cid).is_none());
+		self.edge_ps.insert(cid, ps);
+	}
+
+	pub fn has_face(&self, cid: i32) -> bool {
+		self.faces.contains_key(&cid)
+	}
+
+	// return particle ids of removed edge
+	pub fn remove_edge(&mut self, cid: i32) -> Option<[usize; 2]> {
+		let ps = self.edge_ps.remove(&cid)?;
+		self.edges.remove(&ps);
+		Some(ps)
+	}
+
+	pub fn remove_face(&mut self, cid: i32) {
+		if let Some(ps) = self.faces.remove(&cid) {
+			for p in ps.iter() {
+				if let Some(faces) = self.vfaces.get_mut(p) {
+					faces.retain(|&x| x != cid);
+				}
+			}
+		}
+	}
+
+	pub fn face(&self, cid: i32) -> Option<[usize; 3]> {
+		self.faces.get(&cid).copied()
+	}
+
+	pub fn edge(&self, cid: i32) -> Option<[usize; 2]> {
+		self.edge_ps.get(&cid).copied()
+	}
+
+	pub fn edge_cid(&self, ps: [usize; 2]) -> Option<i32> {
+		self.edges.get(&sorted(ps)).copied()
+	}
+
+	// sorted by constraint id
+	pub fn edges(&self) -> Vec<(i32, [usize; 2])> {
+		let mut result: Vec<_> =
+			self.edge_ps.iter().map(|(&cid, &ps)| (cid, ps)).collect();
+		result.sort_unstable_by_key(|x| x.0);
+		result
+	}
+
+	// faces containing both particles
+	pub fn edge_faces(&self, ps: [usize; 2]) -> Vec<i32> {
+		match self.vfaces.get(&ps[0]) {
+			None => Vec::new(),
+			Some(faces) => faces
+				.iter()
+				.filter(|cid| self.faces[cid].contains(&ps[1]))
+				.copied()
+				.collect(),
+		}
+	}
+
+	pub fn face_count(&self, ps: [usize; 2]) -> usize {
+		self.edge_faces(ps).len()
+	}
+
+	// particles of faces around v, including v
+	pub fn ring(&self, v: usize) -> Vec<usize> {
+		let mut result = vec![v];
+		for cid in self.vfaces.get(&v).into_iter().flatten() {
+			for &w in self.faces[cid].iter() {
+				if !result.contains(&w) {
+					result.push(w);
+				}
+			}
+		}
+		result
+	}
+
+	// edges of faces around v: (constraint id, particle ids)
+	pub fn fan_edges(&self, v: usize) -> Vec<(i32, [usize; 2])> {
+		let mut result = Vec::new();
+		for cid in self.vfaces.get(&v).into_iter().flatten() {
+			for &w in self.faces[cid].iter() {
+				let ps = sorted([v, w]);
+				if let Some(&ecid) = self.edges.get(&ps) {
+					if !result.iter().any(|(x, _)| *x == ecid) {
+						result.push((ecid, ps));
+					}
+				}
+			}
+		}
+		result
+	}
+
+	// faces around v, grouped by connection through remaining edges of v
+	pub fn fan_components(&self, v: usize) -> Vec<Vec<i32>> {
+		let faces = match self.vfaces.get(&v) {
+			None => return Vec::new(),
+			Some(faces) => faces,
+		};
+		let mut group: Vec<usize> = (0..faces.len()).collect();
+		fn root(group: &mut [usize], mut i: usize) -> usize {
+			while group[i] != i {
+				group[i] = group[group[i]];
+				i = group[i];
+			}
+			i
+		}
+		for i in 0..faces.len() {
+			for j in i + 1..faces.len() {
+				let fi = self.faces[&faces[i]];
+				let fj = self.faces[&faces[j]];
+				let connected = fi.iter().any(|&w| {
+					w != v
+						&& fj.contains(&w) && self
+						.edges
+						.contains_key(&sorted([v, w]))
+				});
+				if connected {
+					let ri = root(&mut group, i);
+					let rj = root(&mut group, j);
+					group[ri] = rj;
+				}
+			}
+		}
+		let mut result: Vec<Vec<i32>> = Vec::new();
+		let mut roots: Vec<usize> = Vec::new();
+		for (i, &face) in faces.iter().enumerate() {
+			let r = root(&mut group, i);
+			match roots.iter().position(|&x| x == r) {
+				Some(idx) => result[idx].push(face),
+				None => {
+					roots.push(r);
+					result.push(vec![face]);
+				}
+			}
+		}
+		result
+	}
+
+	// move faces from v to nv, with edges no longer used by faces of v
+	// return constraint ids of moved edges
+	pub fn split_vertex(
+		&mut self,
+		v: usize,
+		nv: usize,
+		faces: &[i32],
+	) -> Vec<i32> {
+		let mut ws = Vec::new();
+		for cid in faces.iter() {
+			let face = self.faces.get_mut(cid).unwrap();
+			for p in face.iter_mut() {
+				if *p == v {
+					*p = nv;
+				} else if !ws.contains(p) {
+					ws.push(*p);
+				}
+			}
+			self.vfaces.get_mut(&v).unwrap().retain(|x| x != cid);
+			self.vfaces.entry(nv).or_default().push(*cid);
+		}
+		let mut result = Vec::new();
+		for w in ws.into_iter() {
+			if self.face_count([v, w]) > 0 {
+				continue;
+			}
+			if let Some(cid) = self.edges.remove(&sorted([v, w])) {
+				let ps = sorted([nv, w]);
+				self.edges.insert(ps, cid);
+				self.edge_ps.insert(cid, ps);
+				result.push(cid);
+			}
+		}
+		result
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_split_vertex() {
+		// two triangles sharing edge 0-1, plus a third sharing 0-2
+		let mut t = Topology::default();
+		t.add_face(0, [0, 1, 2]);
+		t.add_face(1, [0, 3, 1]);
+		t.add_face(2, [0, 2, 4]);
+		for (cid, ps) in
+			[[0, 1], [1, 2], [2, 0], [0, 3], [3, 1], [2, 4], [4, 0]]
+				.into_iter()
+				.enumerate()
+		{
+			t.add_edge(10 + cid as i32, ps);
+		}
+		assert_eq!(t.fan_components(0).len(), 1);
+		assert_eq!(t.remove_edge(10), Some([0, 1]));
+		assert_eq!(t.fan_components(0).len(), 2);
+		let components = t.fan_components(0);
+		let moved = components.iter().find(|x| x.contains(&1)).unwrap();
+		assert_eq!(moved, &vec![1]);
+		let edges = t.split_vertex(0, 5, moved);
+		assert_eq!(edges, vec![13]);
+		assert_eq!(t.fan_components(0).len(), 1);
+		assert_eq!(t.fan_components(5).len(), 1);
+		assert_eq!(t.face_count([1, 5]), 1);
+		assert_eq!(t.face_count([0, 1]), 1);
+	}
+}